@@ -17,6 +17,7 @@ fn main() {
 fn main() {
     cc::Build::new()
         .file("src/thorin.c")
+        .define("_GNU_SOURCE", None)
         .flag("-Wno-unused-parameter")
         .flag("-Wno-unused-function")
         .compile("thorin");
@@ -22,22 +22,151 @@ use object::ObjectSection;
 use fallible_iterator::FallibleIterator;
 use std::io::Write;
 use std::path::Path;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
 use std::collections::HashMap;
 use rand::Rng;
 
 
 // these are the C functions defined in thorin.c that do all of the actual
 // system-call stuff.
-// Since rust forbids global mutable state, we need to route the `scope` and `types`
-// globals through the C code.
+// Since rust forbids global mutable state, we need to route the `session`
+// global through the C code.
 extern {
     fn setup(
         child: *const std::os::raw::c_char,
-        callback: unsafe extern fn(*mut Scope, *mut HashMap<String, DerivedType>, libc::uintptr_t, libc::uintptr_t),
-        scope: *mut Scope,
-        types: *mut HashMap<String, DerivedType>
+        argv: *const *const std::os::raw::c_char,
+        envp: *const *const std::os::raw::c_char,
+        cwd: *const std::os::raw::c_char,
+        callback: unsafe extern "C" fn(*mut Session, libc::uintptr_t, libc::uintptr_t, libc::c_int, *const StopInfo) -> libc::c_int,
+        session: *mut Session
+    ) -> libc::c_int;
+    fn attach(
+        pid: libc::pid_t,
+        callback: unsafe extern "C" fn(*mut Session, libc::uintptr_t, libc::uintptr_t, libc::c_int, *const StopInfo) -> libc::c_int,
+        session: *mut Session
+    ) -> libc::c_int;
+    fn configure_inferior(
+        argv: *const *const std::os::raw::c_char,
+        envp: *const *const std::os::raw::c_char,
+        cwd: *const std::os::raw::c_char
     );
+    fn redirect_inferior(
+        stdin: *const std::os::raw::c_char,
+        stdout: *const std::os::raw::c_char,
+        stderr: *const std::os::raw::c_char,
+        use_pty: libc::c_int
+    );
+    fn read_inferior_output(buffer: *mut libc::c_void, size: libc::size_t) -> libc::c_int;
+    fn write_inferior_input(buffer: *const libc::c_void, size: libc::size_t) -> libc::c_int;
+    fn handle_signal(
+        name: *const std::os::raw::c_char,
+        stop: libc::c_int,
+        pass: libc::c_int,
+        print: libc::c_int
+    ) -> libc::c_int;
+    fn print_signals();
+    fn get_signal_name(number: libc::c_int) -> *const std::os::raw::c_char;
     fn read_addr(buffer: *mut libc::c_void, address: libc::uintptr_t, size: libc::size_t);
+    fn set_breakpoint(address: libc::uintptr_t) -> libc::c_int;
+    fn enable_breakpoint(address: libc::uintptr_t, number: libc::c_int) -> libc::c_int;
+    fn delete_breakpoint(number: libc::c_int) -> libc::c_int;
+    fn forget_breakpoint(number: libc::c_int);
+    fn set_temporary_breakpoint(address: libc::uintptr_t) -> libc::c_int;
+    fn set_watchpoint(address: libc::uintptr_t, size: libc::c_int, kind: libc::c_int) -> libc::c_int;
+    fn set_watch_stepping(on: libc::c_int);
+    fn next_breakpoint_number() -> libc::c_int;
+    fn clear_temporary_breakpoints();
+    fn set_scope_breakpoint(address: libc::uintptr_t) -> libc::c_int;
+    fn delete_scope_breakpoint(address: libc::uintptr_t);
+    fn get_registers(registers: *mut Registers) -> libc::c_int;
+    fn get_frame() -> libc::uintptr_t;
+    fn get_xmm_registers(xmm: *mut u8) -> libc::c_int;
+    fn get_thread(
+        index: libc::c_int,
+        number: *mut libc::c_int,
+        tid: *mut libc::pid_t,
+        rip: *mut libc::uintptr_t
+    ) -> libc::c_int;
+    fn select_thread(number: libc::c_int) -> libc::c_int;
+    fn set_fork_mode(follow_child: libc::c_int, detach: libc::c_int);
+    fn set_disable_randomization(on: libc::c_int);
+    fn get_process_id() -> libc::pid_t;
+    fn get_thread_id(count: *mut libc::c_int) -> libc::pid_t;
+    fn get_load_bias() -> libc::uintptr_t;
+    fn get_r_debug() -> libc::uintptr_t;
+    fn set_syscall_tracing(on: libc::c_int);
+    fn make_checkpoint() -> libc::c_int;
+    fn delete_checkpoint(number: libc::c_int) -> libc::c_int;
+    fn start_checkpoint(number: libc::c_int) -> libc::pid_t;
+    fn switch_process(pid: libc::pid_t);
+    fn kill_process(pid: libc::pid_t);
+    fn step_process() -> libc::c_int;
+    fn insert_breakpoints();
+
+    // this one is from the C library, but the libc crate doesn't have it
+    fn strsignal(signo: libc::c_int) -> *const std::os::raw::c_char;
+}
+
+
+// reasons for which the target process can be suspended -- these mirror the
+// STOP_* constants in thorin.c
+const STOP_ENTRY: libc::c_int = 0;
+const STOP_BREAKPOINT: libc::c_int = 1;
+const STOP_STEP: libc::c_int = 3;
+const STOP_ATTACH: libc::c_int = 4;
+const STOP_EXIT: libc::c_int = 5;
+const STOP_WATCHPOINT: libc::c_int = 6;
+const STOP_WATCH_STEP: libc::c_int = 7;
+const STOP_EXEC: libc::c_int = 8;
+const STOP_SWITCH: libc::c_int = 9;
+const STOP_SYSCALL_ENTRY: libc::c_int = 10;
+const STOP_SYSCALL_EXIT: libc::c_int = 11;
+const STOP_CATCHPOINT: libc::c_int = 12;
+const STOP_INTERRUPT: libc::c_int = 13;
+const STOP_LIBRARIES: libc::c_int = 14;
+const STOP_SCOPE: libc::c_int = 15;
+
+// actions that the exception callback can ask thorin.c to perform -- these
+// mirror the ACTION_* constants in thorin.c
+const ACTION_KILL: libc::c_int = 0;
+const ACTION_CONTINUE: libc::c_int = 1;
+const ACTION_STEP: libc::c_int = 2;
+const ACTION_DETACH: libc::c_int = 3;
+const ACTION_RESTART: libc::c_int = 4;
+const ACTION_STOP: libc::c_int = 5;
+
+// kinds of watchpoints -- these mirror the WATCH_* constants in thorin.c
+const WATCH_WRITE: libc::c_int = 0;
+const WATCH_READ: libc::c_int = 1;
+const WATCH_ACCESS: libc::c_int = 2;
+
+
+// the general purpose registers of the target process -- this mirrors
+// `struct registers` in thorin.c
+#[allow(unused)]
+#[repr(C)]
+#[derive(Default, PartialEq)]
+struct Registers {
+    rax: u64, rbx: u64, rcx: u64, rdx: u64, rsi: u64, rdi: u64, rbp: u64, rsp: u64,
+    r8: u64, r9: u64, r10: u64, r11: u64, r12: u64, r13: u64, r14: u64, r15: u64,
+    rip: u64, rflags: u64
+}
+
+
+// why the target process was suspended by a signal -- this mirrors
+// `struct stop_info` in thorin.c
+#[repr(C)]
+struct StopInfo {
+    signo: libc::c_int,
+    code: libc::c_int,
+    address: libc::uintptr_t,
+    sender: libc::c_int,
+    trap: libc::c_int,
+    status: libc::c_int,
+    breakpoint: libc::c_int,
+    watchpoint: libc::c_int,
+    syscall: libc::c_int
 }
 
 
@@ -55,6 +184,7 @@ struct Variable {
 // A scope has an optional name (if it is a function), a set of variables, a set
 // of child scopes, and a program counter range to find the scope within the target
 // process (based on the instruction pointer, i.e RIP register since we're on x86_64)
+// Functions also have the name of their return type, which is empty for void
 #[allow(unused)]
 #[derive(Clone)]
 struct Scope {
@@ -62,17 +192,214 @@ struct Scope {
     variables: HashMap<String, Variable>,
     scopes: Vec<Scope>,
     low_pc: u64,
-    high_pc: u64
+    high_pc: u64,
+    type_name: String
 }
 
 
 // A derived type is either a typedef or struct. It has a name and one of a base
-// type (for typedefs) or list of members (for structs)
+// type (for typedefs) or list of members and size in bytes (for structs)
 #[allow(unused)]
 struct DerivedType {
     name: String,
     base_type: String,
-    members: Vec<Variable>
+    members: Vec<Variable>,
+    byte_size: u64
+}
+
+
+// A line is a row of the line-number program. It maps an address in the target
+// process to a line in a source file. `is_stmt` is set if the address is a good
+// place to stop at when breaking on the line, and `end_sequence` marks the address
+// just past the end of a contiguous block of code
+#[allow(unused)]
+#[derive(Clone, Debug)]
+struct Line {
+    file: String,
+    line: u64,
+    address: u64,
+    is_stmt: bool,
+    end_sequence: bool
+}
+
+
+// A step is a movement of the target process that is still in progress, i.e one
+// that needs the process to be resumed more than once before control goes back
+// to the user
+enum Step {
+    // execute this many more instructions
+    Instructions(u64),
+    // the process has just executed a call instruction. if `step_in` is set and
+    // the function has debug information we stop inside it, otherwise we step
+    // over it
+    IntoCall { step_in: bool },
+    // a temporary breakpoint is set at the return address of the call we are
+    // stepping over, and the stack pointer will have this value once it returns
+    // in thread `tid`
+    OverCall { ret: u64, sp: u64, tid: libc::pid_t },
+    // same as OverCall, but for the function that the process was suspended in.
+    // its return value is printed once it returns
+    Finish { ret: u64, sp: u64, tid: libc::pid_t, function: String, type_name: String }
+}
+
+
+// A line step is a source-level step that is in progress. The target process is
+// stepped until it reaches the beginning of a line other than this one
+struct LineStep {
+    file: String,
+    line: u64,
+    over_calls: bool
+}
+
+
+// A watchpoint watches an expression at `address` for accesses of some `kind`
+// `old` is the value it had the last time we looked, so that we can show
+// how it changed
+// `software` watchpoints are checked after every instruction instead of by the
+// CPU. if a watchpoint watches a local variable, `block` is the address range in
+// which it is valid and `frame`/`sp` are the RBP/RSP of the stack frame it lives
+// in. hardware ones also have a scope breakpoint at `ret`, the frame's return
+// address, so that we notice when the frame is gone
+// a `computed` expression, e.g `a + b`, has no address and is watched by
+// evaluating it instead (software watchpoints only)
+struct Watchpoint {
+    number: libc::c_int,
+    kind: libc::c_int,
+    name: String,
+    type_name: String,
+    address: u64,
+    old: Vec<u8>,
+    software: bool,
+    block: Option<(u64, u64)>,
+    frame: u64,
+    sp: u64,
+    ret: u64,
+    computed: Option<Computed>
+}
+
+
+// A computed watch expression is evaluated with the `variables` that were
+// visible where it was set, in the frame at `base`. `value` is what it was the
+// last time we looked
+struct Computed {
+    variables: HashMap<String, Variable>,
+    base: i64,
+    value: Value
+}
+
+
+// A breakpoint that the user set. thorin.c puts the int3 into the target
+// process, and this decides whether hitting it stops the process: only if it is
+// `enabled`, its `condition` is true and it has been hit more than `ignore`
+// more times. `hits` counts the times it was hit with its condition true, and
+// `temporary` breakpoints are deleted the first time they stop the process, and
+// `dprintf` ones print a message instead of stopping it
+// a breakpoint in a shared library that was unloaded is `pending`: it is out
+// of the process until a library with this location in it is loaded
+struct Breakpoint {
+    number: libc::c_int,
+    address: u64,
+    location: String,
+    condition: Option<String>,
+    ignore: u64,
+    hits: u64,
+    temporary: bool,
+    enabled: bool,
+    dprintf: Option<Dprintf>,
+    pending: Option<String>
+}
+
+
+// A dprintf prints `format` with the values of the expressions in `args`, the
+// way printf would
+struct Dprintf {
+    format: String,
+    args: Vec<String>
+}
+
+
+// A catchpoint stops the target process when it enters or returns from one of
+// `syscalls`, or from any system call if there are none
+struct Catchpoint {
+    number: libc::c_int,
+    syscalls: Vec<u64>,
+    enabled: bool
+}
+
+
+// A shared library that the target process has loaded at `address` (its load
+// bias). its debugging information is in the session along with the program's:
+// `scopes` is how many compilation units it added to the end of the root
+// scope, and `low`/`high` are the first and last addresses it has lines for
+struct Library {
+    path: String,
+    address: u64,
+    scopes: usize,
+    low: u64,
+    high: u64
+}
+
+
+// A checkpoint is a copy of the target process that thorin.c keeps stopped,
+// made when the target process was at `address`
+struct Checkpoint {
+    number: libc::c_int,
+    address: u64
+}
+
+
+// A session holds everything we know about the target program. It lives for
+// as long as thorin does, so that the DWARF information only has to be
+// loaded once no matter how many times the target process is suspended
+// `attached` is set if thorin attached to a process that was already running
+// `args`, `env` and `cwd` are what the target process is started with, `io`
+// holds the files its stdin/stdout/stderr are redirected to, `pty` runs it on
+// its own pseudo-terminal and `disable_randomization` gives it the same memory
+// layout every time. `restart` is set when any of these have changed since it
+// was last started
+// `stop_on_exit` suspends the target process one last time before it exits
+// and `hw_watchpoints` is unset to only use software watchpoints
+// `trace_syscalls` prints every system call the way strace does.
+// `syscall_pending` has the system call of each thread that is waiting for its
+// return value, and `syscall_line` is the thread whose line is still unfinished
+// `origin` is the checkpoint that the target process was last started from (or
+// made), which reverse stepping replays the target process from
+// `load_bias` is how far the addresses in `scope` and `lines` have been moved
+// from the ones in the DWARF file, to where the program is loaded
+// `libraries` are the shared libraries that the target process has loaded
+struct Session {
+    hw_watchpoints: bool,
+    watchpoints: Vec<Watchpoint>,
+    breakpoints: Vec<Breakpoint>,
+    attached: bool,
+    target: String,
+    args: Vec<String>,
+    env: Vec<(OsString, OsString)>,
+    cwd: Option<String>,
+    io: [Option<String>; 3],
+    pty: bool,
+    disable_randomization: bool,
+    restart: bool,
+    run_on_entry: bool,
+    killed: bool,
+    stop_on_exit: bool,
+    follow_fork_child: bool,
+    detach_on_fork: bool,
+    image: String,
+    load_bias: u64,
+    libraries: Vec<Library>,
+    trace_syscalls: bool,
+    catchpoints: Vec<Catchpoint>,
+    syscall_pending: HashMap<libc::pid_t, libc::c_int>,
+    syscall_line: Option<libc::pid_t>,
+    checkpoints: Vec<Checkpoint>,
+    origin: Option<libc::c_int>,
+    scope: Scope,
+    types: HashMap<String, DerivedType>,
+    lines: Vec<Line>,
+    step: Option<Step>,
+    line_step: Option<LineStep>,
+    finished: Option<(String, String)>
 }
 
 
@@ -165,14 +492,24 @@ fn process_variable<'a, 'b>(
             };
             let mut eval = data.evaluation(unit.encoding());
             let mut eval_state = eval.evaluate().unwrap();
+            let mut complete = true;
             while eval_state != gimli::EvaluationResult::Complete {
                 match eval_state {
                     gimli::EvaluationResult::RequiresFrameBase => {
                         eval_state = eval.resume_with_frame_base(0).unwrap();
                     },
-                    _ => unimplemented!()
+                    gimli::EvaluationResult::RequiresRelocatedAddress(addr) => {
+                        eval_state = eval.resume_with_relocated_address(addr).unwrap();
+                    },
+                    // shared libraries are full of locations that we can't
+                    // work out without a running process, which are skipped
+                    _ => {
+                        complete = false;
+                        break;
+                    }
                 }
             }
+            if !complete { break; }
             let eval_result = eval.result();
             if let gimli::Location::Address { address: addr } = eval_result[0].location {
                 offset = Some(addr as i64)
@@ -225,7 +562,8 @@ fn construct_scope<'a, 'b>(
         variables: HashMap::new(),
         scopes: Vec::new(),
         low_pc: 0,
-        high_pc: std::u64::MAX
+        high_pc: u64::MAX,
+        type_name: String::new()
     };
 
     {
@@ -245,6 +583,28 @@ fn construct_scope<'a, 'b>(
                 scope.high_pc = addr;
             }
         });
+
+        dwarf_find_attr!(entry, attr_value, "DW_AT_type", {
+            let u_offset = match attr_value {
+                gimli::AttributeValue::UnitRef(r) => r,
+                _ => { break; }
+            };
+
+            let mut t_entries = unit.entries_at_offset(u_offset).unwrap();
+            let first_entry = match t_entries.next_dfs().unwrap() {
+                Some((_, r)) => r,
+                None => { break; }
+            };
+
+            if first_entry.tag() == gimli::DW_TAG_pointer_type {
+                scope.type_name = String::from("*");
+                break;
+            }
+
+            dwarf_find_attr!(first_entry, t_attr_value, "DW_AT_name", {
+                scope.type_name = String::from(dwarf.attr_string(unit, t_attr_value).unwrap().to_string().unwrap());
+            });
+        });
     }
 
     let mut children = node.children();
@@ -276,7 +636,8 @@ fn construct_global_scope<'a>(
         variables: HashMap::new(),
         scopes: Vec::new(),
         low_pc: 0,
-        high_pc: std::u64::MAX
+        high_pc: u64::MAX,
+        type_name: String::new()
     };
 
     dwarf_iter_units!(dwarf, unit, {
@@ -305,6 +666,7 @@ fn get_types<'a>(dwarf: &'a gimli::Dwarf<gimli::EndianSlice<gimli::LittleEndian>
         let mut name: Option<&str> = None;
         let mut base_type: Option<&str> = None;
         let mut members: Vec<Variable> = Vec::new();
+        let mut byte_size: u64 = 0;
 
         dwarf_find_attr!(entry, attr_value, "DW_AT_name", {
             name = Some(dwarf.attr_string(&unit, attr_value).unwrap().to_string().unwrap());
@@ -333,6 +695,12 @@ fn get_types<'a>(dwarf: &'a gimli::Dwarf<gimli::EndianSlice<gimli::LittleEndian>
         });
 
         if entry.tag() == gimli::DW_TAG_structure_type {
+            dwarf_find_attr!(entry, attr_value, "DW_AT_byte_size", {
+                if let Some(size) = attr_value.udata_value() {
+                    byte_size = size;
+                }
+            });
+
             let mut tree = unit.entries_tree(Some(entry.offset())).unwrap();
             let root = tree.root().unwrap();
             let mut children = root.children();
@@ -349,7 +717,8 @@ fn get_types<'a>(dwarf: &'a gimli::Dwarf<gimli::EndianSlice<gimli::LittleEndian>
             types.insert(String::from(name.unwrap()), DerivedType {
                 name: String::from(name.unwrap()),
                 base_type: String::from(if let Some(s) = base_type { s } else { "" }),
-                members: members
+                members,
+                byte_size
             });
         }
     });
@@ -358,337 +727,4025 @@ fn get_types<'a>(dwarf: &'a gimli::Dwarf<gimli::EndianSlice<gimli::LittleEndian>
 }
 
 
-// this is the entry point of the program
-fn main() {
-    let exec_path = std::env::args().nth(1).expect("Missing argument");
-    let mut dsym_path = exec_path.clone();
-    #[cfg(target_os = "macos")]
-    {
-        dsym_path.push_str(".dSYM/Contents/Resources/DWARF/");
-        dsym_path.push_str(Path::new(&exec_path).file_name().unwrap().to_str().unwrap());
-    }
+// this function runs the line-number program of every compilation unit and
+// collects the rows into a table sorted by address
+fn construct_line_table(dwarf: &gimli::Dwarf<gimli::EndianSlice<gimli::LittleEndian>>) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
 
-    println!("loading DWARF file at {}...", dsym_path);
-    let file = match std::fs::File::open(&dsym_path) {
-        Ok(file) => file,
-        Err(err) => {
-            println!("Error opening file '{}': {}", &dsym_path, err);
-            return;
-        }
-    };
-    let mmapped_file = match unsafe { memmap::Mmap::map(&file) } {
-        Ok(mmapped_file) => mmapped_file,
-        Err(err) => {
-            println!("Could not map file '{}': {}", &dsym_path, err);
-            return;
-        }
-    };
-    let parsed_file = match object::File::parse(&*mmapped_file) {
-        Ok(parsed_file) => parsed_file,
-        Err(err) => {
-            println!("Error parsing file '{}': {}", &dsym_path, err);
-            return;
-        }
-    };
+    dwarf_iter_units!(dwarf, unit, {
+        let program = match unit.line_program.clone() {
+            Some(r) => r,
+            None => { continue; }
+        };
 
-    macro_rules! load_section {
-        ($x:ident, $y:ident) => (
-            gimli::$x::new(
-                &$y,
-                gimli::LittleEndian
-            );
-        )
-    }
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row().unwrap() {
+            let line = match row.line() {
+                Some(r) => r,
+                None => { continue; }
+            };
 
-    let s_debug_info = parsed_file.section_by_name(".debug_info")
-        .expect("No .debug_info section found")
-        .data();
-    let s_debug_abbrev = parsed_file.section_by_name(".debug_abbrev")
-        .expect("No .debug_abbrev section found")
-        .data();
-    let s_debug_str = parsed_file.section_by_name(".debug_str")
-        .expect("No .debug_str section found")
-        .data();
-    let s_debug_line = parsed_file.section_by_name(".debug_line")
-        .expect("No .debug_line section found")
-        .data();
-    let debug_info = load_section!(DebugInfo, s_debug_info);
-    let debug_abbrev = load_section!(DebugAbbrev, s_debug_abbrev);
-    let debug_line = load_section!(DebugLine, s_debug_line);
-    let debug_str = load_section!(DebugStr, s_debug_str);
-    let dwarf = gimli::Dwarf {
-        debug_info,
-        debug_abbrev,
-        debug_line,
-        debug_str,
-        ..Default::default()
-    };
+            let mut file = String::new();
+            if let Some(entry) = row.file(header) {
+                if let Some(dir) = entry.directory(header) {
+                    if let Ok(dir) = dwarf.attr_string(&unit, dir) {
+                        file.push_str(&dir.to_string_lossy());
+                        file.push('/');
+                    }
+                }
 
-    let global_scope = construct_global_scope(&dwarf);
-    let types = get_types(&dwarf);
+                if let Ok(name) = dwarf.attr_string(&unit, entry.path_name()) {
+                    let name = name.to_string_lossy();
+                    if name.starts_with('/') { file.clear(); }
+                    file.push_str(&name);
+                }
+            }
 
-    println!("done.");
-    println!("executing {}...\n", exec_path);
+            lines.push(Line {
+                file,
+                line,
+                address: row.address(),
+                is_stmt: row.is_stmt(),
+                end_sequence: row.end_sequence()
+            });
+        }
+    });
 
-    let exec_path_c = std::ffi::CString::new(String::from(exec_path)).unwrap();
-    let c_scope = Box::new(global_scope);
-    let c_scope_ptr: &'static mut Scope = Box::leak(c_scope);
-    let c_types = Box::new(types);
-    let c_types_ptr: &'static mut HashMap<String, DerivedType> = Box::leak(c_types);
-    unsafe { setup(exec_path_c.as_ptr(), exc_callback, &mut *c_scope_ptr, &mut *c_types_ptr); }
+    lines.sort_by_key(|l| l.address);
+    lines
 }
 
 
-// this function tries to find which scope we're inside of in the suspended
-// target process based on the instruction pointer and the low_pc and high_pc
-// attributes of the scope structs constructed earlier
-// takes O(logn) time because the scope struct is essentially a
-// searchable BTree -- CS225 ftw :)
-fn construct_context(
-    scope: &Scope,
-    variables: &mut HashMap<String, Variable>,
-    scopes: &mut Vec<String>,
-    rip: u64
-) {
-    if let Some(ref name) = scope.name {
-        scopes.push(name.clone());
-    } else {
-        scopes.push(String::from("unnamed scope"));
-    }
+// this function finds the address at which to break for a line in a source file
+// if the line has no code associated with it (a comment or a blank line, for
+// example), we slide forward to the next line that does
+// returns the address and the line that the breakpoint actually landed on
+fn find_line_address(lines: &Vec<Line>, file: &str, line: u64) -> Option<(u64, u64)> {
+    let mut best: Option<(u64, u64)> = None;
 
-    for (name, val) in &(scope.variables) {
-        variables.insert(name.clone(), val.clone());
-    }
+    for row in lines {
+        if !row.is_stmt || row.end_sequence || row.line < line { continue; }
+        if !Path::new(&row.file).ends_with(file) { continue; }
 
-    for child in &(scope.scopes) {
-        if rip >= child.low_pc && rip - child.low_pc <= child.high_pc {
-            construct_context(child, variables, scopes, rip);
-        }
+        best = match best {
+            Some((addr, l)) if l < row.line || (l == row.line && addr <= row.address) => Some((addr, l)),
+            _ => Some((row.address, row.line))
+        };
     }
+
+    best
 }
 
 
-// this macro reads and prints a variable at a specific address in the child process
-macro_rules! print_result_as {
-    ($t:ty, $addr:ident) => {
-        {
-            let size = std::mem::size_of::<$t>();
-            let result: *mut $t = libc::malloc(size) as *mut $t;
-            read_addr(result as *mut libc::c_void, $addr as libc::uintptr_t, size);
-            println!("{}", *result);
-            libc::free(result as *mut libc::c_void);
-        }
+// this function finds the row of the line table that `address` belongs to
+// returns None if there is no line information for the address
+fn find_line(lines: &[Line], address: u64) -> Option<&Line> {
+    let mut index = match lines.binary_search_by_key(&(address + 1), |l| l.address) {
+        Ok(r) | Err(r) => r
     };
 
-    ($t:ty, $addr:ident, $hex:ident) => {
-        {
-            let size = std::mem::size_of::<$t>();
-            let result: *mut $t = libc::malloc(size) as *mut $t;
-            read_addr(result as *mut libc::c_void, $addr as libc::uintptr_t, size);
-            println!("{:#x}", *result);
-            libc::free(result as *mut libc::c_void);
-        }
-    };
+    // rows at the same address are in the order the line-number program
+    // emitted them, so the last one that doesn't end a sequence wins
+    while index > 0 {
+        index -= 1;
+        let row = &lines[index];
+        if !row.end_sequence { return Some(row); }
+        if index == 0 || lines[index - 1].address != row.address { return None; }
+    }
 
-    ($t:ty, $addr:ident, $count:expr, $zero:expr) => {
-        {
-            let size = std::mem::size_of::<$t>() * $count;
-            let mut result: Vec<$t> = vec![$zero; $count];
-            {
-                let slice: &mut [$t] = &mut result;
-                read_addr(slice.as_mut_ptr() as *mut libc::c_void, $addr as libc::uintptr_t, size);
-            }
-            println!("{:?}", result);
-        }
-    };
+    None
 }
 
 
-// this macro resolves the (base) type of a variable and prints it
-macro_rules! print_base_type {
-    ($type_name:ident, $addr:ident, $count:expr) => {
-        match $type_name {
-            "char" | "signed char" | "unsigned char" => {
-                if $count == 1 { print_result_as!(libc::c_char, $addr); }
-                else { print_result_as!(libc::c_char, $addr, ($count), 0); }
-            },
-
-            "short" | "signed short" | "short int" | "signed short int" | "short signed" | "short signed int" => {
-                if $count == 1 { print_result_as!(i16, $addr); }
-                else { print_result_as!(i16, $addr, ($count), 0); }
-            },
-            "unsigned short" | "unsigned short int" | "short unsigned" | "short unsigned int" => {
-                if $count == 1 { print_result_as!(u16, $addr); }
-                else { print_result_as!(u16, $addr, ($count), 0); }
-            },
+// this function names the signal-specific `code` that the OS gives a signal
+// it raised, e.g SEGV_MAPERR for a SIGSEGV from an unmapped address
+fn signal_code(signo: libc::c_int, code: libc::c_int) -> Option<(&'static str, &'static str)> {
+    let codes: &[(&str, &str)] = match signo {
+        libc::SIGSEGV => &[
+            ("SEGV_MAPERR", "address not mapped to object"),
+            ("SEGV_ACCERR", "invalid permissions for mapped object")
+        ],
+        libc::SIGBUS => &[
+            ("BUS_ADRALN", "invalid address alignment"),
+            ("BUS_ADRERR", "nonexistent physical address"),
+            ("BUS_OBJERR", "object-specific hardware error")
+        ],
+        libc::SIGFPE => &[
+            ("FPE_INTDIV", "integer divide by zero"),
+            ("FPE_INTOVF", "integer overflow"),
+            ("FPE_FLTDIV", "floating-point divide by zero"),
+            ("FPE_FLTOVF", "floating-point overflow"),
+            ("FPE_FLTUND", "floating-point underflow"),
+            ("FPE_FLTRES", "floating-point inexact result"),
+            ("FPE_FLTINV", "floating-point invalid operation"),
+            ("FPE_FLTSUB", "subscript out of range")
+        ],
+        libc::SIGILL => &[
+            ("ILL_ILLOPC", "illegal opcode"),
+            ("ILL_ILLOPN", "illegal operand"),
+            ("ILL_ILLADR", "illegal addressing mode"),
+            ("ILL_ILLTRP", "illegal trap"),
+            ("ILL_PRVOPC", "privileged opcode"),
+            ("ILL_PRVREG", "privileged register"),
+            ("ILL_COPROC", "coprocessor error"),
+            ("ILL_BADSTK", "internal stack error")
+        ],
+        _ => &[]
+    };
 
-            "int" | "signed int" | "signed" => {
-                if $count == 1 { print_result_as!(i16, $addr); }
-                else { print_result_as!(i16, $addr, ($count), 0); }
-            },
-            "unsigned int" | "unsigned" => {
-                if $count == 1 { print_result_as!(u16, $addr); }
-                else { print_result_as!(u16, $addr, ($count), 0); }
-            },
+    // the codes of each signal are numbered from 1
+    if code > 0 && (code as usize) <= codes.len() {
+        return Some(codes[code as usize - 1]);
+    }
 
-            "long" | "signed long" | "long int" | "signed long int" | "long signed" | "long signed int" => {
-                if $count == 1 { print_result_as!(i32, $addr); }
-                else { print_result_as!(i32, $addr, ($count), 0); }
-            },
-            "unsigned long" | "unsigned long int" | "long unsigned" | "long unsigned int" => {
-                if $count == 1 { print_result_as!(u32, $addr); }
-                else { print_result_as!(u32, $addr, ($count), 0); }
-            },
+    // these are shared by all signals, and say who sent it. the libc crate
+    // doesn't have them, so these are the values from linux's siginfo.h
+    match code {
+        0 => Some(("SI_USER", "sent by kill()")),
+        0x80 => Some(("SI_KERNEL", "sent by the kernel")),
+        -1 => Some(("SI_QUEUE", "sent by sigqueue()")),
+        -2 => Some(("SI_TIMER", "timer expired")),
+        -6 => Some(("SI_TKILL", "sent by tkill() or raise()")),
+        _ => None
+    }
+}
 
-            "long long" | "signed long long" | "long long int" | "signed long long int" | "long long signed" | "long long signed int" => {
-                if $count == 1 { print_result_as!(i64, $addr); }
-                else { print_result_as!(i64, $addr, ($count), 0); }
-            },
-            "unsigned long long" | "unsigned long long int" | "long long unsigned" | "long long unsigned int" => {
-                if $count == 1 { print_result_as!(u64, $addr); }
-                else { print_result_as!(u64, $addr, ($count), 0); }
-            },
 
-            "float" => {
-                if $count == 1 { print_result_as!(f32, $addr); }
-                else { print_result_as!(f32, $addr, ($count), 0.0); }
-            },
-            "double" => {
-                if $count == 1 { print_result_as!(f64, $addr); }
-                else { print_result_as!(f64, $addr, ($count), 0.0); }
-            }
+// this function tells the user why the target process was suspended by a signal
+unsafe fn print_stop_info(info: &StopInfo) {
+    if info.signo == 0 {
+        println!("Process suspended.\n");
+        return;
+    }
 
-            "*" => {
-                if $count == 1 { print_result_as!(u64, $addr, $addr); }
-                else { print_result_as!(u64, $addr, ($count), 0); }
-            }
+    let name = std::ffi::CStr::from_ptr(get_signal_name(info.signo)).to_string_lossy();
+    let description = std::ffi::CStr::from_ptr(strsignal(info.signo)).to_string_lossy();
+    println!("Program received signal {}, {}.", name, description);
 
-            _ => { println!("unknown type"); }
+    match signal_code(info.signo, info.code) {
+        Some((code_name, code_description)) => { println!("  reason: {} ({})", code_description, code_name); },
+        None => { println!("  reason: unknown (si_code {})", info.code); }
+    }
+    if info.address != 0 {
+        println!("  faulting address: {:#x}", info.address);
+    }
+    if info.sender != 0 {
+        println!("  sent by process {}", info.sender);
+    }
+    if info.trap != 0 {
+        if info.signo == libc::SIGILL {
+            println!("  the program executed a trap instruction (ud2), e.g from __builtin_trap()");
+        } else {
+            println!("  the program executed a hard-coded breakpoint (int3)");
         }
-    };
+    }
+    println!();
 }
 
 
-// this function recursively resolves the (derived) type of a variable and prints it
-fn print_struct(offset: &str, varname: &str, type_name: &str, addr: i64, types: &HashMap<String, DerivedType>) {
-    print!("{}{} {}: ", offset, type_name, varname);
-    let d_type = types.get(type_name);
-    if let Some(ref dt) = d_type {
-        println!("");
-        let new_offset = format!("  {}", offset);
-        if dt.members.len() > 0 {
-            for member in &dt.members {
-                let new_addr = addr + member.offset;
-                print_struct(&new_offset, &member.name, &member.type_name, new_addr, types);
-            }
-        } else {
-            print_struct(&new_offset, varname, &dt.base_type, addr, types);
-        }
-    } else {
-        unsafe { print_base_type!(type_name, addr, 1); }
-    }
+// how the arguments of a system call are shown
+#[derive(Clone, Copy)]
+enum SyscallArg {
+    Int,       // a signed number
+    Hex,       // a pointer, or anything else that's best shown in hex
+    Str,       // a NUL-terminated string
+    Buf,       // a buffer, whose length is the next argument
+    Fd,        // a file descriptor
+    DirFd,     // a directory file descriptor, which can be AT_FDCWD
+    OpenFlags, // O_* flags
+    Mode,      // file permissions
+    Prot,      // PROT_* flags
+    MapFlags,  // MAP_* flags
+    Signal     // a signal number
 }
 
 
-// this function reads an arbitrary address in the child process as a specific type and prints the results
-unsafe fn read_ptr(address: u64, count: usize, type_name: &str, types: &HashMap<String, DerivedType>) {
-    let d_type = types.get(type_name);
+// a system call on x86_64: its number, name and arguments, and whether it
+// returns an address
+struct Syscall {
+    number: u64,
+    name: &'static str,
+    args: &'static [SyscallArg],
+    address: bool
+}
 
-    if d_type.is_none() {
-        print_base_type!(type_name, address, (count));
-    } else {
-        if d_type.unwrap().members.len() > 0 {
-            println!("cannot read structs yet"); return;
-        }
-        read_ptr(address, count, &d_type.unwrap().base_type, types);
-    }
+
+// this macro makes a Syscall entry with the given argument kinds
+macro_rules! syscall {
+    ($number:expr, $name:expr, [$($arg:ident),*]) => (
+        Syscall { number: $number, name: $name, args: &[$(SyscallArg::$arg),*], address: false }
+    );
+    ($number:expr, $name:expr, [$($arg:ident),*], address) => (
+        Syscall { number: $number, name: $name, args: &[$(SyscallArg::$arg),*], address: true }
+    );
 }
 
 
-// this is the exception callback -- it gets called when the target process is suspended
-// and starts the main debugger loop
-unsafe extern "C" fn exc_callback(
-    scope_p: *mut Scope,
-    types_p: *mut HashMap<String, DerivedType>,
-    rbp: libc::uintptr_t,
-    rip: libc::uintptr_t
-) {
-    println!("Process suspended.\n");
+// the system calls that we know how to show. anything else is shown with its
+// number and six arguments in hex
+static SYSCALLS: &[Syscall] = &[
+    syscall!(0, "read", [Fd, Hex, Int]),
+    syscall!(1, "write", [Fd, Buf, Int]),
+    syscall!(2, "open", [Str, OpenFlags, Mode]),
+    syscall!(3, "close", [Fd]),
+    syscall!(4, "stat", [Str, Hex]),
+    syscall!(5, "fstat", [Fd, Hex]),
+    syscall!(6, "lstat", [Str, Hex]),
+    syscall!(7, "poll", [Hex, Int, Int]),
+    syscall!(8, "lseek", [Fd, Int, Int]),
+    syscall!(9, "mmap", [Hex, Int, Prot, MapFlags, Fd, Int], address),
+    syscall!(10, "mprotect", [Hex, Int, Prot]),
+    syscall!(11, "munmap", [Hex, Int]),
+    syscall!(12, "brk", [Hex], address),
+    syscall!(13, "rt_sigaction", [Signal, Hex, Hex, Int]),
+    syscall!(14, "rt_sigprocmask", [Int, Hex, Hex, Int]),
+    syscall!(15, "rt_sigreturn", []),
+    syscall!(16, "ioctl", [Fd, Hex, Hex]),
+    syscall!(17, "pread64", [Fd, Hex, Int, Int]),
+    syscall!(18, "pwrite64", [Fd, Buf, Int, Int]),
+    syscall!(19, "readv", [Fd, Hex, Int]),
+    syscall!(20, "writev", [Fd, Hex, Int]),
+    syscall!(21, "access", [Str, Int]),
+    syscall!(22, "pipe", [Hex]),
+    syscall!(23, "select", [Int, Hex, Hex, Hex, Hex]),
+    syscall!(24, "sched_yield", []),
+    syscall!(25, "mremap", [Hex, Int, Int, Int, Hex], address),
+    syscall!(28, "madvise", [Hex, Int, Int]),
+    syscall!(32, "dup", [Fd]),
+    syscall!(33, "dup2", [Fd, Fd]),
+    syscall!(34, "pause", []),
+    syscall!(35, "nanosleep", [Hex, Hex]),
+    syscall!(37, "alarm", [Int]),
+    syscall!(39, "getpid", []),
+    syscall!(40, "sendfile", [Fd, Fd, Hex, Int]),
+    syscall!(41, "socket", [Int, Int, Int]),
+    syscall!(42, "connect", [Fd, Hex, Int]),
+    syscall!(43, "accept", [Fd, Hex, Hex]),
+    syscall!(44, "sendto", [Fd, Buf, Int, Int, Hex, Int]),
+    syscall!(45, "recvfrom", [Fd, Hex, Int, Int, Hex, Hex]),
+    syscall!(46, "sendmsg", [Fd, Hex, Int]),
+    syscall!(47, "recvmsg", [Fd, Hex, Int]),
+    syscall!(48, "shutdown", [Fd, Int]),
+    syscall!(49, "bind", [Fd, Hex, Int]),
+    syscall!(50, "listen", [Fd, Int]),
+    syscall!(51, "getsockname", [Fd, Hex, Hex]),
+    syscall!(52, "getpeername", [Fd, Hex, Hex]),
+    syscall!(53, "socketpair", [Int, Int, Int, Hex]),
+    syscall!(54, "setsockopt", [Fd, Int, Int, Hex, Int]),
+    syscall!(55, "getsockopt", [Fd, Int, Int, Hex, Hex]),
+    syscall!(56, "clone", [Hex, Hex, Hex, Hex, Hex]),
+    syscall!(57, "fork", []),
+    syscall!(58, "vfork", []),
+    syscall!(59, "execve", [Str, Hex, Hex]),
+    syscall!(60, "exit", [Int]),
+    syscall!(61, "wait4", [Int, Hex, Int, Hex]),
+    syscall!(62, "kill", [Int, Signal]),
+    syscall!(63, "uname", [Hex]),
+    syscall!(72, "fcntl", [Fd, Int, Hex]),
+    syscall!(74, "fsync", [Fd]),
+    syscall!(76, "truncate", [Str, Int]),
+    syscall!(77, "ftruncate", [Fd, Int]),
+    syscall!(78, "getdents", [Fd, Hex, Int]),
+    syscall!(79, "getcwd", [Hex, Int]),
+    syscall!(80, "chdir", [Str]),
+    syscall!(81, "fchdir", [Fd]),
+    syscall!(82, "rename", [Str, Str]),
+    syscall!(83, "mkdir", [Str, Mode]),
+    syscall!(84, "rmdir", [Str]),
+    syscall!(86, "link", [Str, Str]),
+    syscall!(87, "unlink", [Str]),
+    syscall!(88, "symlink", [Str, Str]),
+    syscall!(89, "readlink", [Str, Hex, Int]),
+    syscall!(90, "chmod", [Str, Mode]),
+    syscall!(91, "fchmod", [Fd, Mode]),
+    syscall!(95, "umask", [Mode]),
+    syscall!(96, "gettimeofday", [Hex, Hex]),
+    syscall!(97, "getrlimit", [Int, Hex]),
+    syscall!(99, "sysinfo", [Hex]),
+    syscall!(102, "getuid", []),
+    syscall!(104, "getgid", []),
+    syscall!(105, "setuid", [Int]),
+    syscall!(106, "setgid", [Int]),
+    syscall!(107, "geteuid", []),
+    syscall!(108, "getegid", []),
+    syscall!(110, "getppid", []),
+    syscall!(112, "setsid", []),
+    syscall!(131, "sigaltstack", [Hex, Hex]),
+    syscall!(137, "statfs", [Str, Hex]),
+    syscall!(138, "fstatfs", [Fd, Hex]),
+    syscall!(157, "prctl", [Int, Hex, Hex, Hex, Hex]),
+    syscall!(158, "arch_prctl", [Int, Hex]),
+    syscall!(186, "gettid", []),
+    syscall!(200, "tkill", [Int, Signal]),
+    syscall!(201, "time", [Hex]),
+    syscall!(202, "futex", [Hex, Int, Int, Hex, Hex, Int]),
+    syscall!(204, "sched_getaffinity", [Int, Int, Hex]),
+    syscall!(213, "epoll_create", [Int]),
+    syscall!(217, "getdents64", [Fd, Hex, Int]),
+    syscall!(218, "set_tid_address", [Hex]),
+    syscall!(228, "clock_gettime", [Int, Hex]),
+    syscall!(229, "clock_getres", [Int, Hex]),
+    syscall!(230, "clock_nanosleep", [Int, Int, Hex, Hex]),
+    syscall!(231, "exit_group", [Int]),
+    syscall!(232, "epoll_wait", [Fd, Hex, Int, Int]),
+    syscall!(233, "epoll_ctl", [Fd, Int, Fd, Hex]),
+    syscall!(234, "tgkill", [Int, Int, Signal]),
+    syscall!(257, "openat", [DirFd, Str, OpenFlags, Mode]),
+    syscall!(258, "mkdirat", [DirFd, Str, Mode]),
+    syscall!(262, "newfstatat", [DirFd, Str, Hex, Int]),
+    syscall!(263, "unlinkat", [DirFd, Str, Int]),
+    syscall!(264, "renameat", [DirFd, Str, DirFd, Str]),
+    syscall!(267, "readlinkat", [DirFd, Str, Hex, Int]),
+    syscall!(268, "fchmodat", [DirFd, Str, Mode]),
+    syscall!(269, "faccessat", [DirFd, Str, Int]),
+    syscall!(270, "pselect6", [Int, Hex, Hex, Hex, Hex, Hex]),
+    syscall!(271, "ppoll", [Hex, Int, Hex, Hex, Int]),
+    syscall!(273, "set_robust_list", [Hex, Int]),
+    syscall!(281, "epoll_pwait", [Fd, Hex, Int, Int, Hex, Int]),
+    syscall!(288, "accept4", [Fd, Hex, Hex, Int]),
+    syscall!(290, "eventfd2", [Int, Int]),
+    syscall!(291, "epoll_create1", [Int]),
+    syscall!(292, "dup3", [Fd, Fd, OpenFlags]),
+    syscall!(293, "pipe2", [Hex, OpenFlags]),
+    syscall!(302, "prlimit64", [Int, Int, Hex, Hex]),
+    syscall!(316, "renameat2", [DirFd, Str, DirFd, Str, Int]),
+    syscall!(318, "getrandom", [Hex, Int, Int]),
+    syscall!(322, "execveat", [DirFd, Str, Hex, Hex, Int]),
+    syscall!(332, "statx", [DirFd, Str, Int, Int, Hex]),
+    syscall!(334, "rseq", [Hex, Int, Int, Int]),
+    syscall!(435, "clone3", [Hex, Int]),
+    syscall!(437, "openat2", [DirFd, Str, Hex, Int]),
+    syscall!(439, "faccessat2", [DirFd, Str, Int, Int])
+];
 
-    let mut variables: HashMap<String, Variable> = HashMap::new();
-    let mut scopes: Vec<String> = Vec::new();
-    let scope = &(*scope_p);
-    construct_context(scope, &mut variables, &mut scopes, rip as u64);
 
-    let types = &(*types_p);
+// the kernel's values of the O_* flags on x86_64, other than the access mode
+// in the lowest two bits. O_SYNC and O_TMPFILE include other flags, so they
+// have to come first
+const OPEN_FLAGS: &[(u64, &str)] = &[
+    (0o4010000, "O_SYNC"), (0o20200000, "O_TMPFILE"), (0o100, "O_CREAT"), (0o200, "O_EXCL"),
+    (0o400, "O_NOCTTY"), (0o1000, "O_TRUNC"), (0o2000, "O_APPEND"), (0o4000, "O_NONBLOCK"),
+    (0o10000, "O_DSYNC"), (0o20000, "O_ASYNC"), (0o40000, "O_DIRECT"), (0o100000, "O_LARGEFILE"),
+    (0o200000, "O_DIRECTORY"), (0o400000, "O_NOFOLLOW"), (0o1000000, "O_NOATIME"),
+    (0o2000000, "O_CLOEXEC"), (0o10000000, "O_PATH")
+];
 
-    println!("Scope tree:");
-    let mut scope_print_offset = String::from("");
-    for scope_name in scopes {
-        println!("{}-> {}", scope_print_offset, scope_name);
-        scope_print_offset.push_str("  ");
+const PROT_FLAGS: &[(u64, &str)] = &[
+    (0x1, "PROT_READ"), (0x2, "PROT_WRITE"), (0x4, "PROT_EXEC")
+];
+
+const MAP_FLAGS: &[(u64, &str)] = &[
+    (0x3, "MAP_SHARED_VALIDATE"), (0x1, "MAP_SHARED"), (0x2, "MAP_PRIVATE"), (0x10, "MAP_FIXED"),
+    (0x20, "MAP_ANONYMOUS"), (0x100, "MAP_GROWSDOWN"), (0x800, "MAP_DENYWRITE"),
+    (0x1000, "MAP_EXECUTABLE"), (0x2000, "MAP_LOCKED"), (0x4000, "MAP_NORESERVE"),
+    (0x8000, "MAP_POPULATE"), (0x10000, "MAP_NONBLOCK"), (0x20000, "MAP_STACK"),
+    (0x40000, "MAP_HUGETLB"), (0x100000, "MAP_FIXED_NOREPLACE")
+];
+
+// the names of the errno values
+const ERRNO_NAMES: &[(libc::c_int, &str)] = &[
+    (libc::EPERM, "EPERM"), (libc::ENOENT, "ENOENT"), (libc::ESRCH, "ESRCH"), (libc::EINTR, "EINTR"),
+    (libc::EIO, "EIO"), (libc::ENXIO, "ENXIO"), (libc::E2BIG, "E2BIG"), (libc::ENOEXEC, "ENOEXEC"),
+    (libc::EBADF, "EBADF"), (libc::ECHILD, "ECHILD"), (libc::EAGAIN, "EAGAIN"), (libc::ENOMEM, "ENOMEM"),
+    (libc::EACCES, "EACCES"), (libc::EFAULT, "EFAULT"), (libc::EBUSY, "EBUSY"), (libc::EEXIST, "EEXIST"),
+    (libc::EXDEV, "EXDEV"), (libc::ENODEV, "ENODEV"), (libc::ENOTDIR, "ENOTDIR"), (libc::EISDIR, "EISDIR"),
+    (libc::EINVAL, "EINVAL"), (libc::ENFILE, "ENFILE"), (libc::EMFILE, "EMFILE"), (libc::ENOTTY, "ENOTTY"),
+    (libc::ETXTBSY, "ETXTBSY"), (libc::EFBIG, "EFBIG"), (libc::ENOSPC, "ENOSPC"), (libc::ESPIPE, "ESPIPE"),
+    (libc::EROFS, "EROFS"), (libc::EMLINK, "EMLINK"), (libc::EPIPE, "EPIPE"), (libc::EDOM, "EDOM"),
+    (libc::ERANGE, "ERANGE"), (libc::EDEADLK, "EDEADLK"), (libc::ENAMETOOLONG, "ENAMETOOLONG"),
+    (libc::ENOLCK, "ENOLCK"), (libc::ENOSYS, "ENOSYS"), (libc::ENOTEMPTY, "ENOTEMPTY"), (libc::ELOOP, "ELOOP"),
+    (libc::ENOMSG, "ENOMSG"), (libc::ENODATA, "ENODATA"), (libc::ETIME, "ETIME"), (libc::EOVERFLOW, "EOVERFLOW"),
+    (libc::ENOTSOCK, "ENOTSOCK"), (libc::EDESTADDRREQ, "EDESTADDRREQ"), (libc::EMSGSIZE, "EMSGSIZE"),
+    (libc::EPROTOTYPE, "EPROTOTYPE"), (libc::ENOPROTOOPT, "ENOPROTOOPT"),
+    (libc::EPROTONOSUPPORT, "EPROTONOSUPPORT"), (libc::EOPNOTSUPP, "EOPNOTSUPP"),
+    (libc::EAFNOSUPPORT, "EAFNOSUPPORT"), (libc::EADDRINUSE, "EADDRINUSE"),
+    (libc::EADDRNOTAVAIL, "EADDRNOTAVAIL"), (libc::ENETDOWN, "ENETDOWN"), (libc::ENETUNREACH, "ENETUNREACH"),
+    (libc::ECONNABORTED, "ECONNABORTED"), (libc::ECONNRESET, "ECONNRESET"), (libc::ENOBUFS, "ENOBUFS"),
+    (libc::EISCONN, "EISCONN"), (libc::ENOTCONN, "ENOTCONN"), (libc::ETIMEDOUT, "ETIMEDOUT"),
+    (libc::ECONNREFUSED, "ECONNREFUSED"), (libc::EHOSTUNREACH, "EHOSTUNREACH"), (libc::EALREADY, "EALREADY"),
+    (libc::EINPROGRESS, "EINPROGRESS"), (libc::ECANCELED, "ECANCELED")
+];
+
+// errno values that are only used inside the kernel, which a traced process
+// can see when a system call is interrupted and is about to be restarted. the
+// C library doesn't know what they mean
+const RESTART_ERRNOS: &[(libc::c_int, &str, &str)] = &[
+    (512, "ERESTARTSYS", "To be restarted if SA_RESTART is set"),
+    (513, "ERESTARTNOINTR", "To be restarted"),
+    (514, "ERESTARTNOHAND", "To be restarted if no handler"),
+    (516, "ERESTART_RESTARTBLOCK", "Interrupted by signal")
+];
+
+
+// this function finds a system call by its number
+fn find_syscall(number: u64) -> Option<&'static Syscall> {
+    SYSCALLS.iter().find(|s| s.number == number)
+}
+
+
+// this function finds the number of a system call from its name or number
+fn syscall_number(name: &str) -> Option<u64> {
+    if let Some(syscall) = SYSCALLS.iter().find(|s| s.name == name) {
+        return Some(syscall.number);
+    }
+
+    name.parse::<u64>().ok()
+}
+
+
+// this function shows `value` as a combination of `flags`, with whatever bits
+// are left over in hex
+fn format_flags(value: u64, flags: &[(u64, &str)]) -> String {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = value;
+    for &(bits, name) in flags {
+        if rest & bits == bits {
+            names.push(String::from(name));
+            rest &= !bits;
+        }
+    }
+    if rest != 0 || names.is_empty() {
+        names.push(format!("{:#x}", rest));
+    }
+
+    names.join("|")
+}
+
+
+// this function reads a string out of the target process to show it as a
+// system call argument. `length` is how many bytes there are, or None if the
+// string ends with a NUL. long strings are cut off
+fn read_syscall_string(address: u64, length: Option<u64>) -> String {
+    const MAX_LENGTH: usize = 32;
+    if address == 0 {
+        return String::from("NULL");
+    }
+
+    // one byte more than we show tells us whether the string was cut off
+    let limit = match length {
+        Some(l) => std::cmp::min(l as usize, MAX_LENGTH + 1),
+        None => MAX_LENGTH + 1
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; 8];
+    let mut done = false;
+    while !done && bytes.len() < limit {
+        read_process(chunk.as_mut_ptr() as *mut libc::c_void, address + bytes.len() as u64, chunk.len());
+        for &byte in chunk.iter() {
+            if bytes.len() == limit || (length.is_none() && byte == 0) {
+                done = true;
+                break;
+            }
+            bytes.push(byte);
+        }
+    }
+
+    let truncated = bytes.len() > MAX_LENGTH;
+    bytes.truncate(MAX_LENGTH);
+
+    let mut text = String::from("\"");
+    for &byte in &bytes {
+        match byte {
+            b'\n' => { text.push_str("\\n"); },
+            b'\t' => { text.push_str("\\t"); },
+            b'"' => { text.push_str("\\\""); },
+            b'\\' => { text.push_str("\\\\"); },
+            0x20..=0x7e => { text.push(byte as char); },
+            _ => { text.push_str(&format!("\\x{:02x}", byte)); }
+        }
+    }
+    text.push('"');
+    if truncated {
+        text.push_str("...");
+    }
+
+    text
+}
+
+
+// this function shows a system call argument. `next` is the argument after
+// it, which is the length of buffers
+unsafe fn format_syscall_arg(kind: SyscallArg, value: u64, next: u64) -> String {
+    match kind {
+        SyscallArg::Int => format!("{}", value as i64),
+        SyscallArg::Fd => format!("{}", value as i32),
+        SyscallArg::Hex => if value == 0 { String::from("NULL") } else { format!("{:#x}", value) },
+        SyscallArg::Str => read_syscall_string(value, None),
+        SyscallArg::Buf => read_syscall_string(value, Some(next)),
+        SyscallArg::DirFd => if value as i32 == libc::AT_FDCWD { String::from("AT_FDCWD") } else { format!("{}", value as i32) },
+        SyscallArg::OpenFlags => {
+            let access = ["O_RDONLY", "O_WRONLY", "O_RDWR", "O_ACCMODE"][(value & 3) as usize];
+            if value & !3 == 0 { String::from(access) } else { format!("{}|{}", access, format_flags(value & !3, OPEN_FLAGS)) }
+        },
+        SyscallArg::Mode => if value == 0 { String::from("0") } else { format!("0{:o}", value) },
+        SyscallArg::Prot => if value == 0 { String::from("PROT_NONE") } else { format_flags(value, PROT_FLAGS) },
+        SyscallArg::MapFlags => format_flags(value, MAP_FLAGS),
+        SyscallArg::Signal => String::from(std::ffi::CStr::from_ptr(get_signal_name(value as libc::c_int)).to_string_lossy())
+    }
+}
+
+
+// this function shows system call `number` with its arguments, which are
+// still in the registers of the target process, the way strace would
+unsafe fn format_syscall(number: libc::c_int) -> String {
+    let mut regs = Registers::default();
+    get_registers(&mut regs);
+
+    // the kernel takes the arguments in these registers, in this order
+    let values = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+    let syscall = match find_syscall(number as u64) {
+        Some(s) => s,
+        None => {
+            let args: Vec<String> = values.iter().map(|v| format!("{:#x}", v)).collect();
+            return format!("syscall_{}({})", number, args.join(", "));
+        }
+    };
+
+    let mut args: Vec<String> = Vec::new();
+    for (i, kind) in syscall.args.iter().enumerate() {
+        args.push(format_syscall_arg(*kind, values[i], if i < 5 { values[i + 1] } else { 0 }));
+    }
+
+    format!("{}({})", syscall.name, args.join(", "))
+}
+
+
+// this function shows what system call `number` returned, which is in RAX.
+// values from -4095 to -1 are errors
+unsafe fn format_syscall_return(number: libc::c_int) -> String {
+    let mut regs = Registers::default();
+    get_registers(&mut regs);
+
+    let value = regs.rax as i64;
+    if (-4095..0).contains(&value) {
+        let errno = -value as libc::c_int;
+        if let Some(e) = RESTART_ERRNOS.iter().find(|e| e.0 == errno) {
+            return format!("? {} ({})", e.1, e.2);
+        }
+
+        let name = match ERRNO_NAMES.iter().find(|e| e.0 == errno) {
+            Some(e) => String::from(e.1),
+            None => format!("errno {}", errno)
+        };
+        let description = std::ffi::CStr::from_ptr(libc::strerror(errno)).to_string_lossy();
+        return format!("-1 {} ({})", name, description);
+    }
+
+    match find_syscall(number as u64) {
+        Some(syscall) if syscall.address => format!("{:#x}", value),
+        _ => format!("{}", value)
+    }
+}
+
+
+// this function finds the name of system call `number`
+fn syscall_name(number: libc::c_int) -> String {
+    match find_syscall(number as u64) {
+        Some(syscall) => String::from(syscall.name),
+        None => format!("syscall_{}", number)
+    }
+}
+
+
+// this function finds the catchpoint that catches system call `number`
+fn find_catchpoint(catchpoints: &[Catchpoint], number: libc::c_int) -> Option<libc::c_int> {
+    catchpoints.iter()
+        .find(|c| c.enabled && (c.syscalls.is_empty() || c.syscalls.contains(&(number as u64))))
+        .map(|c| c.number)
+}
+
+
+// this function deals with a system call that the target process is entering
+// or returning from. it returns true if a catchpoint caught it, and otherwise
+// prints it if we're tracing system calls
+// like strace, lines are prefixed with the thread they are for once there are
+// several threads, and a thread's line is left unfinished when another thread
+// makes a system call before it returns
+unsafe fn catch_syscall(session: &mut Session, reason: libc::c_int, number: libc::c_int) -> bool {
+    let caught = find_catchpoint(&session.catchpoints, number).is_some();
+    let name = syscall_name(number);
+    let mut count: libc::c_int = 0;
+    let tid = get_thread_id(&mut count);
+
+    // a line that is still waiting for its return value can only be finished
+    // by the same system call in the same thread
+    let pending = if reason == STOP_SYSCALL_ENTRY || caught {
+        session.syscall_pending.remove(&tid);
+        None
+    } else {
+        session.syscall_pending.remove(&tid)
+    };
+    let line = session.syscall_line.take();
+    let finishes_line = line == Some(tid) && pending == Some(number);
+    if line.is_some() && !finishes_line {
+        println!(" <unfinished ...>");
+    }
+    if caught || !session.trace_syscalls {
+        return caught;
+    }
+
+    let prefix = if count > 1 { format!("[pid {:>5}] ", tid) } else { String::new() };
+    if reason == STOP_SYSCALL_ENTRY {
+        print!("{}{}", prefix, format_syscall(number));
+        match name.as_ref() {
+            // these never return
+            "exit" | "exit_group" => { println!(" = ?"); },
+            _ => {
+                session.syscall_pending.insert(tid, number);
+                session.syscall_line = Some(tid);
+            }
+        }
+        std::io::stdout().flush().unwrap();
+    } else {
+        if !finishes_line {
+            print!("{}<... {} resumed>", prefix, name);
+        }
+        println!(" = {}", format_syscall_return(number));
+    }
+
+    false
+}
+
+
+// this function replaces the debugging information in the session with the
+// one for the executable at `exec_path`, when the process starts running a
+// different program. if there isn't any, nothing in the program can be
+// looked up anymore
+fn switch_image(session: &mut Session, exec_path: &str) {
+    let dsym_path = dwarf_path(exec_path);
+    println!("loading DWARF file at {}...", dsym_path);
+    match load_debug_info(&dsym_path) {
+        Ok((scope, types, lines)) => {
+            session.scope = scope;
+            session.types = types;
+            session.lines = lines;
+            println!("done.\n");
+        },
+        Err(err) => {
+            println!("{}\n", err);
+            session.scope = Scope {
+                name: Some(String::from("root")),
+                variables: HashMap::new(),
+                scopes: Vec::new(),
+                low_pc: 0,
+                high_pc: u64::MAX,
+                type_name: String::new()
+            };
+            session.types = HashMap::new();
+            session.lines = Vec::new();
+        }
+    }
+
+    session.image = String::from(exec_path);
+    session.load_bias = 0;
+    session.libraries.clear();
+}
+
+
+// this function moves the program counter range of `scope` and its children
+// by `delta`
+fn relocate_scope(scope: &mut Scope, delta: u64) {
+    // the root scope and ones without a range don't have an address to move
+    if scope.low_pc != 0 {
+        scope.low_pc = scope.low_pc.wrapping_add(delta);
+    }
+    for child in &mut scope.scopes {
+        relocate_scope(child, delta);
+    }
+}
+
+
+// this function moves every address that came from the debugging information,
+// and the breakpoints that were set at them, to where the program was loaded
+// this time. position-independent programs can be loaded anywhere
+fn relocate(session: &mut Session, load_bias: u64) {
+    let delta = load_bias.wrapping_sub(session.load_bias);
+    relocate_scope(&mut session.scope, delta);
+    for line in &mut session.lines {
+        line.address = line.address.wrapping_add(delta);
+    }
+    for breakpoint in session.breakpoints.iter_mut().filter(|b| b.pending.is_none()) {
+        breakpoint.address = breakpoint.address.wrapping_add(delta);
+    }
+
+    session.load_bias = load_bias;
+}
+
+
+// how many shared libraries we look for in the dynamic linker's list, in case
+// it is corrupted and loops
+const MAX_LIBRARIES: usize = 1024;
+
+
+// this function reads the pointer-sized value at `address` in the target process
+fn read_pointer(address: u64) -> u64 {
+    let mut value: u64 = 0;
+    read_process(&mut value as *mut u64 as *mut libc::c_void, address, 8);
+    value
+}
+
+
+// this function finds the file that the dynamic linker loaded a shared library
+// from. names that aren't absolute are relative to the directory that process
+// `pid` runs in. returns None for names that aren't files, like the vDSO's
+fn library_path(pid: libc::pid_t, name: &str) -> Option<String> {
+    if name.is_empty() { return None; }
+
+    let path = Path::new(&format!("/proc/{}/cwd", pid)).join(name);
+    let path = std::fs::canonicalize(path).ok()?;
+    if !path.is_file() { return None; }
+
+    Some(path.to_string_lossy().into_owned())
+}
+
+
+// this function loads the debugging information of the shared library at
+// `path` into the session, moved to `address` where it was loaded. pending
+// breakpoints that are in the library are put into the process
+unsafe fn load_library(session: &mut Session, path: String, address: u64) {
+    let mut library = Library { path, address, scopes: 0, low: 0, high: 0 };
+    if let Ok((mut scope, types, mut lines)) = load_debug_info(&dwarf_path(&library.path)) {
+        relocate_scope(&mut scope, address);
+        for line in &mut lines {
+            line.address = line.address.wrapping_add(address);
+        }
+        if let (Some(first), Some(last)) = (lines.first(), lines.last()) {
+            library.low = first.address;
+            library.high = last.address;
+        }
+
+        library.scopes = scope.scopes.len();
+        session.scope.scopes.extend(scope.scopes);
+        for (name, derived_type) in types {
+            session.types.entry(name).or_insert(derived_type);
+        }
+        session.lines.extend(lines);
+        session.lines.sort_by_key(|l| l.address);
+    }
+    session.libraries.push(library);
+
+    for breakpoint in session.breakpoints.iter_mut().filter(|b| b.pending.is_some()) {
+        let address = match resolve_location(&session.scope, &session.lines, breakpoint.pending.as_ref().unwrap()) {
+            Ok((address, _)) => address,
+            Err(_) => { continue; }
+        };
+        if breakpoint.enabled && enable_breakpoint(address as libc::uintptr_t, breakpoint.number) == -1 {
+            continue;
+        }
+
+        breakpoint.address = address;
+        breakpoint.pending = None;
+    }
+}
+
+
+// this function takes the shared library at `index` and its debugging
+// information out of the session. breakpoints in it become pending, so that
+// they come back if it is loaded again
+unsafe fn unload_library(session: &mut Session, index: usize) {
+    let library = session.libraries.remove(index);
+    let after: usize = session.libraries[index..].iter().map(|l| l.scopes).sum();
+    let end = session.scope.scopes.len() - after;
+    session.scope.scopes.drain(end - library.scopes..end);
+
+    let inside = |address: u64| library.high != 0 && address >= library.low && address <= library.high;
+    for breakpoint in session.breakpoints.iter_mut() {
+        if breakpoint.pending.is_some() || !inside(breakpoint.address) { continue; }
+
+        // the library can be loaded somewhere else next time, but the line
+        // is the same
+        let row = match find_line(&session.lines, breakpoint.address) {
+            Some(r) => r,
+            None => { continue; }
+        };
+        if breakpoint.enabled { forget_breakpoint(breakpoint.number); }
+        breakpoint.pending = Some(format!("{}:{}", row.file, row.line));
+    }
+    session.lines.retain(|l| !inside(l.address));
+}
+
+
+// this function takes every shared library out of the session, e.g before
+// the program is started again
+unsafe fn unload_libraries(session: &mut Session) {
+    while !session.libraries.is_empty() {
+        let last = session.libraries.len() - 1;
+        unload_library(session, last);
+    }
+}
+
+
+// this function brings the shared libraries in the session up to date with the
+// dynamic linker's list of them (struct r_debug and struct link_map in
+// <link.h>), loading new ones and unloading the ones that are gone
+unsafe fn update_libraries(session: &mut Session) {
+    let r_debug = get_r_debug() as u64;
+    if r_debug == 0 { return; }
+
+    // the list is only complete while the dynamic linker isn't changing it
+    let mut state: libc::c_int = 0;
+    read_process(&mut state as *mut libc::c_int as *mut libc::c_void, r_debug + 24, 4);
+    if state != 0 { return; }
+
+    let pid = get_process_id();
+    let mut loaded: Vec<(String, u64)> = Vec::new();
+    let mut map = read_pointer(r_debug + 8);
+    for _ in 0..MAX_LIBRARIES {
+        if map == 0 { break; }
+
+        let address = read_pointer(map);
+        let name = read_c_string(read_pointer(map + 8), libc::PATH_MAX as usize);
+        if let Some(path) = library_path(pid, &name) {
+            loaded.push((path, address));
+        }
+        map = read_pointer(map + 24);
+    }
+
+    let mut index = session.libraries.len();
+    while index > 0 {
+        index -= 1;
+        let library = &session.libraries[index];
+        if !loaded.iter().any(|(p, a)| *p == library.path && *a == library.address) {
+            unload_library(session, index);
+        }
+    }
+    for (path, address) in loaded {
+        if !session.libraries.iter().any(|l| l.path == path && l.address == address) {
+            load_library(session, path, address);
+        }
+    }
+}
+
+
+// this function lists the shared libraries that the program has loaded
+fn print_libraries(session: &Session) {
+    if session.libraries.is_empty() {
+        println!("No shared libraries loaded at this time.");
+        return;
+    }
+
+    println!("{:<18} {:<10} Shared Object Library", "Address", "Syms Read");
+    for library in &session.libraries {
+        let symbols = if library.scopes > 0 { "Yes" } else { "No" };
+        println!("{:<18} {:<10} {}", format!("{:#x}", library.address), symbols, library.path);
+    }
+}
+
+
+// this function prints the source file location of `address` and the line of
+// source code there, if we can find it
+fn print_location(lines: &[Line], address: u64) {
+    let row = match find_line(lines, address) {
+        Some(r) => r,
+        None => { return; }
+    };
+
+    println!("{}:{}", row.file, row.line);
+    if let Ok(source) = std::fs::read_to_string(&row.file) {
+        if let Some(text) = source.lines().nth(row.line as usize - 1) {
+            println!("{}\t{}", row.line, text);
+        }
+    }
+    println!();
+}
+
+
+// this function lists the threads of the target process along with the
+// function and source line that each of them is stopped at. the thread that
+// the user is looking at is marked with a '*'
+unsafe fn print_threads(scope: &Scope, lines: &[Line]) {
+    let mut index = 0;
+    loop {
+        let mut number: libc::c_int = 0;
+        let mut tid: libc::pid_t = 0;
+        let mut rip: libc::uintptr_t = 0;
+        let current = get_thread(index, &mut number, &mut tid, &mut rip);
+        if current == -1 { break; }
+
+        let function = find_function_at(scope, rip as u64)
+            .and_then(|f| f.name.clone())
+            .unwrap_or(String::from("??"));
+        let location = match find_line(lines, rip as u64) {
+            Some(row) => format!(" at {}:{}", row.file, row.line),
+            None => String::new()
+        };
+
+        let marker = if current == 1 { "*" } else { " " };
+        println!("{} {:<3} Thread {} in {} ({:#x}){}", marker, number, tid, function, rip, location);
+        index += 1;
+    }
+}
+
+
+// this function lists the checkpoints, marking the one that the target process
+// was started from
+fn print_checkpoints(session: &Session) {
+    if session.checkpoints.is_empty() {
+        println!("No checkpoints.");
+        return;
+    }
+
+    for checkpoint in &session.checkpoints {
+        let function = find_function_at(&session.scope, checkpoint.address)
+            .and_then(|f| f.name.clone())
+            .unwrap_or(String::from("??"));
+        let location = match find_line(&session.lines, checkpoint.address) {
+            Some(row) => format!(" at {}:{}", row.file, row.line),
+            None => String::new()
+        };
+
+        let marker = if session.origin == Some(checkpoint.number) { "*" } else { " " };
+        println!("{} {:<3} Checkpoint in {} ({:#x}){}", marker, checkpoint.number, function, checkpoint.address, location);
+    }
+}
+
+
+// this function finds the file that holds the debugging information for the
+// executable at `exec_path`
+fn dwarf_path(exec_path: &str) -> String {
+    let dsym_path = String::from(exec_path);
+    #[cfg(target_os = "macos")]
+    let dsym_path = {
+        let mut dsym_path = dsym_path;
+        dsym_path.push_str(".dSYM/Contents/Resources/DWARF/");
+        dsym_path.push_str(Path::new(&exec_path).file_name().unwrap().to_str().unwrap());
+        dsym_path
+    };
+
+    dsym_path
+}
+
+
+// the scope tree, type map and line table of a program or library
+type DebugInfo = (Scope, HashMap<String, DerivedType>, Vec<Line>);
+
+
+// this function reads the DWARF file at `dsym_path` and builds the scope
+// tree, type map and line table out of it
+fn load_debug_info(dsym_path: &str) -> Result<DebugInfo, String> {
+    let file = match std::fs::File::open(&dsym_path) {
+        Ok(file) => file,
+        Err(err) => {
+            return Err(format!("Error opening file '{}': {}", &dsym_path, err));
+        }
+    };
+    let mmapped_file = match unsafe { memmap::Mmap::map(&file) } {
+        Ok(mmapped_file) => mmapped_file,
+        Err(err) => {
+            return Err(format!("Could not map file '{}': {}", &dsym_path, err));
+        }
+    };
+    let parsed_file = match object::File::parse(&*mmapped_file) {
+        Ok(parsed_file) => parsed_file,
+        Err(err) => {
+            return Err(format!("Error parsing file '{}': {}", &dsym_path, err));
+        }
+    };
+
+    macro_rules! load_section {
+        ($x:ident, $y:ident) => (
+            gimli::$x::new(
+                &$y,
+                gimli::LittleEndian
+            );
+        )
+    }
+
+    macro_rules! section_data {
+        ($name:expr) => (
+            match parsed_file.section_by_name($name) {
+                Some(section) => section.data(),
+                None => { return Err(format!("No {} section found", $name)); }
+            }
+        )
+    }
+
+    let s_debug_info = section_data!(".debug_info");
+    let s_debug_abbrev = section_data!(".debug_abbrev");
+    let s_debug_str = section_data!(".debug_str");
+    let s_debug_line = section_data!(".debug_line");
+    let debug_info = load_section!(DebugInfo, s_debug_info);
+    let debug_abbrev = load_section!(DebugAbbrev, s_debug_abbrev);
+    let debug_line = load_section!(DebugLine, s_debug_line);
+    let debug_str = load_section!(DebugStr, s_debug_str);
+    let dwarf = gimli::Dwarf {
+        debug_info,
+        debug_abbrev,
+        debug_line,
+        debug_str,
+        ..Default::default()
+    };
+
+    let global_scope = construct_global_scope(&dwarf);
+    let types = get_types(&dwarf);
+    let lines = construct_line_table(&dwarf);
+
+    Ok((global_scope, types, lines))
+}
+
+
+// this is the entry point of the program
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mut attach_pid: Option<libc::pid_t> = None;
+
+    // 'thorin trace-syscalls <program>' runs the program straight away and
+    // prints its system calls as they happen
+    let trace_syscalls = args.len() > 2 && args[1] == "trace-syscalls";
+    if trace_syscalls {
+        args.remove(1);
+    }
+
+    let exec_path = if args.len() > 1 && args[1] == "--pid" {
+        let pid = match args.get(2).map(|p| p.parse::<libc::pid_t>()) {
+            Some(Ok(r)) => r,
+            _ => {
+                println!("Usage: thorin --pid <pid>");
+                return;
+            }
+        };
+        attach_pid = Some(pid);
+
+        // the executable of a running process can be found through procfs
+        match std::fs::read_link(format!("/proc/{}/exe", pid)) {
+            Ok(r) => String::from(r.to_string_lossy()),
+            Err(err) => {
+                println!("Could not find the executable of process {}: {}", pid, err);
+                return;
+            }
+        }
+    } else {
+        args.get(1).expect("Missing argument").clone()
+    };
+
+    // everything after the program is passed on to it, with an optional
+    // leading '--' to separate it from our own arguments
+    let mut program_args: Vec<String> = if attach_pid.is_some() { Vec::new() } else {
+        args.iter().skip(2).cloned().collect()
+    };
+    if program_args.first().map(|a| a == "--").unwrap_or(false) {
+        program_args.remove(0);
+    }
+
+    let dsym_path = dwarf_path(&exec_path);
+    println!("loading DWARF file at {}...", dsym_path);
+    let (global_scope, types, lines) = match load_debug_info(&dsym_path) {
+        Ok(r) => r,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    println!("done.");
+    if let Some(pid) = attach_pid {
+        println!("attaching to process {}...\n", pid);
+    } else {
+        println!("executing {}...\n", exec_path);
+    }
+
+    let exec_path_c = std::ffi::CString::new(exec_path.clone()).unwrap();
+    let c_session = Box::new(Session {
+        attached: attach_pid.is_some(),
+        target: exec_path.clone(),
+        args: program_args,
+        env: std::env::vars_os().collect(),
+        cwd: None,
+        io: [None, None, None],
+        pty: false,
+        disable_randomization: true,
+        restart: false,
+        run_on_entry: trace_syscalls,
+        killed: false,
+        stop_on_exit: false,
+        follow_fork_child: false,
+        detach_on_fork: true,
+        image: exec_path,
+        load_bias: 0,
+        libraries: Vec::new(),
+        trace_syscalls,
+        catchpoints: Vec::new(),
+        syscall_pending: HashMap::new(),
+        syscall_line: None,
+        checkpoints: Vec::new(),
+        origin: None,
+        watchpoints: Vec::new(),
+        breakpoints: Vec::new(),
+        hw_watchpoints: true,
+        scope: global_scope,
+        types,
+        lines,
+        step: None,
+        line_step: None,
+        finished: None
+    });
+    let c_session_ptr: &'static mut Session = Box::leak(c_session);
+    let status = unsafe {
+        if trace_syscalls {
+            set_syscall_tracing(1);
+        }
+        let status = match attach_pid {
+            Some(pid) => attach(pid, exc_callback, &mut *c_session_ptr),
+            None => {
+                let (argv, envp, cwd) = inferior_strings(c_session_ptr);
+                let argv_p = null_terminated(&argv);
+                let envp_p = null_terminated(&envp);
+                let cwd_p = cwd.as_ref().map(|c| c.as_ptr()).unwrap_or(std::ptr::null());
+                setup(exec_path_c.as_ptr(), argv_p.as_ptr(), envp_p.as_ptr(), cwd_p, exc_callback, &mut *c_session_ptr)
+            }
+        };
+        drop(Box::from_raw(c_session_ptr));
+        status
+    };
+
+    println!();
+    let mut rng = rand::thread_rng();
+    match rng.gen_range(0, 4) {
+        0 => { println!("\"If more people valued home, above gold, this world would be a merrier place...\""); },
+        1 => { println!("\"We are sons of Durin. And Durin's Folk do not flee from a fight.\""); },
+        2 => { println!("\"Those who have lived through dragon fire should rejoice. They have much to be grateful for.\""); },
+        _ => { println!("\"If this is to end in fire, then we will all burn together.\""); }
+    }
+    println!();
+
+    // exit the way the target program did, so that thorin can be used in scripts
+    std::process::exit(status);
+}
+
+
+// this function converts the arguments, environment and working directory of
+// a session into the C strings that execve expects
+// argv[0] is always the path to the target program
+fn inferior_strings(
+    session: &Session
+) -> (Vec<std::ffi::CString>, Vec<std::ffi::CString>, Option<std::ffi::CString>) {
+    let mut argv = vec![std::ffi::CString::new(session.target.clone()).unwrap()];
+    for arg in &session.args {
+        argv.push(std::ffi::CString::new(arg.clone()).unwrap());
+    }
+
+    let envp = session.env.iter()
+        .map(|(name, value)| {
+            let variable = [name.as_bytes(), b"=", value.as_bytes()].concat();
+            std::ffi::CString::new(variable).unwrap()
+        })
+        .collect();
+    let cwd = session.cwd.as_ref().map(|c| std::ffi::CString::new(c.clone()).unwrap());
+
+    (argv, envp, cwd)
+}
+
+
+// this function builds the NULL-terminated array of pointers that C expects
+// from a list of strings. the strings must outlive the array
+fn null_terminated(strings: &[std::ffi::CString]) -> Vec<*const std::os::raw::c_char> {
+    let mut pointers: Vec<_> = strings.iter().map(|s| s.as_ptr()).collect();
+    pointers.push(std::ptr::null());
+    pointers
+}
+
+
+// this function hands the settings of a session over to thorin.c, so that the
+// target process is started with them the next time
+unsafe fn configure(session: &Session) {
+    let (argv, envp, cwd) = inferior_strings(session);
+    let argv_p = null_terminated(&argv);
+    let envp_p = null_terminated(&envp);
+    let cwd_p = cwd.as_ref().map(|c| c.as_ptr()).unwrap_or(std::ptr::null());
+    configure_inferior(argv_p.as_ptr(), envp_p.as_ptr(), cwd_p);
+
+    let io: Vec<_> = session.io.iter()
+        .map(|f| f.as_ref().map(|f| std::ffi::CString::new(f.clone()).unwrap()))
+        .collect();
+    let io_p: Vec<_> = io.iter()
+        .map(|f| f.as_ref().map(|f| f.as_ptr()).unwrap_or(std::ptr::null()))
+        .collect();
+    redirect_inferior(io_p[0], io_p[1], io_p[2], session.pty as libc::c_int);
+}
+
+
+// this function gets the session ready for the program to be started again,
+// and returns the action that makes thorin.c do it. the debugging information
+// and breakpoints are kept, but watchpoints and steps belonged to the old
+// process. if `run` is set, the program doesn't wait for another 'run'
+unsafe fn respawn(session: &mut Session, run: bool) -> libc::c_int {
+    configure(session);
+    unload_libraries(session);
+    session.restart = false;
+    session.run_on_entry = run;
+    session.watchpoints.clear();
+    set_watch_stepping(0);
+    session.step = None;
+    session.line_step = None;
+    session.finished = None;
+    session.checkpoints.clear();
+    session.origin = None;
+
+    ACTION_RESTART
+}
+
+
+// how far reverse stepping replays the target process before giving up, and
+// how much of its stack is compared to tell whether a replay got back to where
+// it was
+const MAX_REPLAY_STEPS: u64 = 1000000;
+const MAX_STACK_SNAPSHOT: u64 = 65536;
+
+
+// this function reads where the thread that the user is looking at is stopped,
+// as the RBP and RIP values that thorin.c passes to the callback
+unsafe fn current_frame() -> Option<(libc::uintptr_t, libc::uintptr_t)> {
+    let mut regs = Registers::default();
+    let frame = get_frame();
+    if frame == 0 || get_registers(&mut regs) == -1 { return None; }
+
+    Some((frame, regs.rip as libc::uintptr_t))
+}
+
+
+// this function finds the end of the stack of process `pid`
+fn stack_end(pid: libc::pid_t) -> Option<u64> {
+    let maps = std::fs::read_to_string(format!("/proc/{}/maps", pid)).ok()?;
+    let line = maps.lines().find(|l| l.ends_with("[stack]"))?;
+    let range = line.split_whitespace().next()?;
+    let end = range.split('-').nth(1)?;
+
+    u64::from_str_radix(end, 16).ok()
+}
+
+
+// this function records the state of the target process: its registers and
+// the top `size` bytes of its stack. the flags are left out, since the trap
+// flag differs depending on how the process got there
+unsafe fn take_snapshot(size: u64) -> Option<(Registers, Vec<u8>)> {
+    let mut regs = Registers::default();
+    if get_registers(&mut regs) == -1 { return None; }
+    regs.rflags = 0;
+
+    let mut stack = vec![0u8; size as usize];
+    read_addr(stack.as_mut_ptr() as *mut libc::c_void, regs.rsp as libc::uintptr_t, size as libc::size_t);
+
+    Some((regs, stack))
+}
+
+
+// this function finds the line that the target process starts executing at
+// `rip`, if it is at the start of one. the start of a function doesn't count,
+// since stepping into a function stops after its prologue
+fn line_start_at<'a>(scope: &Scope, lines: &'a [Line], rip: u64) -> Option<&'a Line> {
+    let row = find_line(lines, rip)?;
+    if row.address != rip || !row.is_stmt { return None; }
+    if find_function_at(scope, rip).map(|f| f.low_pc == rip).unwrap_or(false) { return None; }
+
+    Some(row)
+}
+
+
+// this function moves the target process back to the start of the line that
+// it executed before the one it is in. there is no way to run a process
+// backwards, so a copy of the checkpoint it started from is replayed until it
+// gets to where the process is, and another copy is then replayed up to the
+// last line start on the way. `over_calls` skips lines in functions that were
+// called from the current one
+// the process is replaced by the second copy
+unsafe fn reverse_step(session: &Session, over_calls: bool) -> Result<(), String> {
+    let origin = match session.origin {
+        Some(r) => r,
+        None => { return Err(String::from("there is no checkpoint to replay from, make one with 'checkpoint'")); }
+    };
+
+    let current = get_process_id();
+    let end = stack_end(current).ok_or(String::from("cannot find the stack of the program"))?;
+    let (target, stack) = {
+        let mut regs = Registers::default();
+        if get_registers(&mut regs) == -1 { return Err(String::from("cannot read the registers of the program")); }
+        take_snapshot(std::cmp::min(end.saturating_sub(regs.rsp), MAX_STACK_SNAPSHOT))
+            .ok_or(String::from("cannot read the registers of the program"))?
+    };
+    let target_line = find_line(&session.lines, target.rip);
+
+    // the first replay counts the instructions up to each line start, and the
+    // one that the process is in doesn't count
+    if start_checkpoint(origin) == -1 { return Err(format!("cannot start checkpoint {}", origin)); }
+    let mut starts: Vec<(u64, &Line)> = Vec::new();
+    let mut count: u64 = 0;
+    let found = loop {
+        let mut regs = Registers::default();
+        if get_registers(&mut regs) == -1 { break false; }
+        if regs.rip == target.rip && regs.rsp == target.rsp
+            && take_snapshot(stack.len() as u64).map(|(r, s)| r == target && s == stack).unwrap_or(false) {
+            break true;
+        }
+
+        if let Some(row) = line_start_at(&session.scope, &session.lines, regs.rip) {
+            let new_line = starts.last().map(|(_, l)| l.line != row.line || l.file != row.file).unwrap_or(true);
+            if new_line && (!over_calls || regs.rsp >= target.rsp) { starts.push((count, row)); }
+        }
+
+        if count == MAX_REPLAY_STEPS || step_process() == -1 { break false; }
+        count += 1;
+    };
+
+    let replay = get_process_id();
+    switch_process(current);
+    kill_process(replay);
+    if !found {
+        return Err(format!("could not replay the program from checkpoint {} to where it is", origin));
+    }
+
+    if let (Some((_, last)), Some(line)) = (starts.last(), target_line) {
+        if last.line == line.line && last.file == line.file { starts.pop(); }
+    }
+    let destination = match starts.last() {
+        Some((r, _)) => *r,
+        None => { return Err(format!("no line was started since checkpoint {}", origin)); }
+    };
+
+    // the second replay stops there and takes the place of the process
+    if start_checkpoint(origin) == -1 { return Err(format!("cannot start checkpoint {}", origin)); }
+    for _ in 0..destination {
+        if step_process() == -1 {
+            let replay = get_process_id();
+            switch_process(current);
+            kill_process(replay);
+            return Err(format!("could not replay the program from checkpoint {}", origin));
+        }
+    }
+    kill_process(current);
+    insert_breakpoints();
+
+    Ok(())
+}
+
+
+// this function splits the arguments of 'run' into the program's arguments and
+// the files that its stdin ('<'), stdout ('>') and stderr ('2>') are
+// redirected to. the file name can be attached to the operator or follow it
+fn parse_redirections(words: &[&str]) -> Result<(Vec<String>, [Option<String>; 3]), String> {
+    let mut args = Vec::new();
+    let mut io = [None, None, None];
+
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+        let (stream, rest) = if let Some(rest) = word.strip_prefix("2>") {
+            (2, rest)
+        } else if let Some(rest) = word.strip_prefix('>') {
+            (1, rest)
+        } else if let Some(rest) = word.strip_prefix('<') {
+            (0, rest)
+        } else {
+            args.push(word.to_string());
+            i += 1;
+            continue;
+        };
+
+        let file = if !rest.is_empty() { rest } else {
+            i += 1;
+            match words.get(i) {
+                Some(r) => *r,
+                None => { return Err(format!("missing file name after '{}'", word)); }
+            }
+        };
+        io[stream] = Some(file.to_string());
+        i += 1;
+    }
+
+    Ok((args, io))
+}
+
+
+// this function finds the address of a breakpoint location, which is either a
+// function or <file>:<line>, and describes where it is
+unsafe fn resolve_location(scope: &Scope, lines: &Vec<Line>, location: &str) -> Result<(u64, String), String> {
+    if let Some(colon) = location.rfind(':') {
+        let file = &location[..colon];
+        let line = match location[colon + 1..].parse::<u64>() {
+            Ok(r) => r,
+            Err(err) => { return Err(format!("error parsing line number: {}", err)); }
+        };
+
+        return match find_line_address(lines, file, line) {
+            Some((address, actual_line)) => Ok((address, format!("file {}, line {}", file, actual_line))),
+            None => Err(format!("no code found at or after line {} in '{}'.", line, file))
+        };
+    }
+
+    match find_function(scope, location) {
+        Some(function) => Ok((skip_prologue(lines, function), String::from(location))),
+        None => Err(format!("unrecognized function '{}'.", location))
+    }
+}
+
+
+// this function finds the function (subprogram) scope with a given name
+fn find_function<'a>(scope: &'a Scope, name: &str) -> Option<&'a Scope> {
+    for child in &(scope.scopes) {
+        if let Some(ref child_name) = child.name {
+            if child_name == name && child.low_pc != 0 {
+                return Some(child);
+            }
+        }
+
+        if let Some(r) = find_function(child, name) {
+            return Some(r);
+        }
+    }
+
+    None
+}
+
+
+// this function finds the function (subprogram) scope that contains `pc`
+// functions are the children of compilation units, which are the children of
+// the root scope
+fn find_function_at(scope: &Scope, pc: u64) -> Option<&Scope> {
+    for unit in &(scope.scopes) {
+        for function in &(unit.scopes) {
+            if function.low_pc != 0 && pc >= function.low_pc && pc - function.low_pc < function.high_pc {
+                return Some(function);
+            }
+        }
+    }
+
+    None
+}
+
+
+// this function finds the address just past a function's prologue, i.e the
+// code that sets up its stack frame
+// variables are found relative to RBP, so they can only be read once the
+// frame has been set up
+// compilers emit a second row in the line table for the first line of the function
+// body, right after the prologue. if there isn't one we look for the usual
+// `push %rbp; mov %rsp,%rbp` sequence instead
+unsafe fn skip_prologue(lines: &[Line], function: &Scope) -> u64 {
+    let low_pc = function.low_pc;
+    if let Some(row) = lines.iter().find(|l| l.address > low_pc && l.is_stmt) {
+        if !row.end_sequence && row.address - low_pc < function.high_pc {
+            return row.address;
+        }
+    }
+
+    let mut code: [u8; 8] = [0; 8];
+    read_addr(code.as_mut_ptr() as *mut libc::c_void, low_pc as libc::uintptr_t, code.len());
+
+    let mut pc = 0;
+    if code[pc..pc + 4] == [0xf3, 0x0f, 0x1e, 0xfa] { pc += 4; } // endbr64
+    if code[pc] == 0x55 && code[pc + 1..pc + 4] == [0x48, 0x89, 0xe5] {
+        return low_pc + pc as u64 + 4;
+    }
+
+    low_pc
+}
+
+
+// this function finds where `function`'s frame keeps the saved RBP, with the
+// return address right above it
+// inside the prologue the frame isn't set up yet: the return address is on top
+// of the stack until `push %rbp`, and right above it until `mov %rsp,%rbp`
+unsafe fn frame_address(lines: &[Line], function: &Scope, rip: u64, regs: &Registers) -> u64 {
+    if rip >= skip_prologue(lines, function) { return regs.rbp; }
+
+    let mut code: [u8; 8] = [0; 8];
+    read_addr(code.as_mut_ptr() as *mut libc::c_void, function.low_pc as libc::uintptr_t, code.len());
+
+    let mut pc = 0;
+    if code[pc..pc + 4] == [0xf3, 0x0f, 0x1e, 0xfa] { pc += 4; } // endbr64
+    if code[pc] != 0x55 || rip <= function.low_pc + pc as u64 { return regs.rsp - 8; }
+    if code[pc + 1..pc + 4] != [0x48, 0x89, 0xe5] || rip <= function.low_pc + pc as u64 + 1 { return regs.rsp; }
+
+    regs.rbp
+}
+
+
+// this function checks whether the instruction at `address` is a (near) call,
+// i.e either `call rel32` or `call r/m64`
+unsafe fn is_call_instruction(address: u64) -> bool {
+    let mut code: [u8; 16] = [0; 16];
+    read_addr(code.as_mut_ptr() as *mut libc::c_void, address as libc::uintptr_t, code.len());
+
+    // skip legacy prefixes and the REX prefix
+    let mut pc = 0;
+    while pc < code.len() - 2 {
+        match code[pc] {
+            0x26 | 0x2e | 0x36 | 0x3e | 0x64 | 0x65 | 0x66 | 0x67 | 0xf2 | 0xf3 => { pc += 1; },
+            _ => { break; }
+        }
+    }
+    if code[pc] & 0xf0 == 0x40 { pc += 1; }
+
+    code[pc] == 0xe8 || (code[pc] == 0xff && (code[pc + 1] >> 3) & 7 == 2)
+}
+
+
+// this function checks whether the process has hit the temporary breakpoint at
+// `ret` in a deeper (recursive) call of the function we are waiting on, in which
+// case the breakpoint is set again so that we catch the right return
+unsafe fn returned_from_recursion(rip: u64, regs: &Registers, ret: u64, sp: u64) -> bool {
+    rip == ret && regs.rsp < sp && set_temporary_breakpoint(ret as libc::uintptr_t) != -1
+}
+
+
+// this function checks whether a thread other than `tid`, the one we are
+// stepping, has hit the temporary breakpoint at `ret`, in which case the
+// breakpoint is set again for `tid`
+unsafe fn returned_in_other_thread(rip: u64, ret: u64, tid: libc::pid_t) -> bool {
+    let mut count: libc::c_int = 0;
+    rip == ret && get_thread_id(&mut count) != tid && set_temporary_breakpoint(ret as libc::uintptr_t) != -1
+}
+
+
+// this function finds the size in bytes of a type
+fn type_size(type_name: &str, types: &HashMap<String, DerivedType>) -> u64 {
+    if let Some(dt) = types.get(type_name) {
+        if !dt.members.is_empty() { return dt.byte_size; }
+        return type_size(&dt.base_type, types);
+    }
+
+    match type_name {
+        "char" | "signed char" | "unsigned char" => 1,
+        "short" | "signed short" | "short int" | "signed short int" | "short signed" | "short signed int"
+            | "unsigned short" | "unsigned short int" | "short unsigned" | "short unsigned int" => 2,
+        "int" | "signed int" | "signed" | "unsigned int" | "unsigned" | "float" => 4,
+        _ => 8
+    }
+}
+
+
+// this function finds out which eightbytes of a struct only contain floating point
+// members. the SysV x86_64 ABI returns those in SSE registers, and the others in
+// general purpose registers
+fn classify_eightbytes(type_name: &str, offset: u64, types: &HashMap<String, DerivedType>, sse: &mut [bool; 2]) {
+    if let Some(dt) = types.get(type_name) {
+        if !dt.members.is_empty() {
+            for member in &dt.members {
+                classify_eightbytes(&member.type_name, offset + member.offset as u64, types, sse);
+            }
+        } else {
+            classify_eightbytes(&dt.base_type, offset, types, sse);
+        }
+
+        return;
+    }
+
+    if type_name != "float" && type_name != "double" && offset < 16 {
+        sse[(offset / 8) as usize] = false;
+    }
+}
+
+
+// this function prints the value that a function returned, which the SysV x86_64
+// ABI puts in RAX/RDX or XMM0/XMM1 depending on its type
+// structs larger than 16 bytes are returned in memory, and RAX holds their address
+unsafe fn print_return_value(function: &str, type_name: &str, types: &HashMap<String, DerivedType>) {
+    if type_name.is_empty() {
+        println!("{} returned.\n", function);
+        return;
+    }
+
+    let mut regs = Registers::default();
+    let mut xmm: [u8; 256] = [0; 256];
+    if get_registers(&mut regs) == -1 || get_xmm_registers(xmm.as_mut_ptr()) == -1 {
+        return;
+    }
+
+    let size = type_size(type_name, types);
+    let varname = format!("{}()", function);
+    println!("Value returned:");
+
+    let is_struct = types.get(type_name).is_some_and(|dt| !dt.members.is_empty());
+    if is_struct && size > 16 {
+        print_struct("", &varname, type_name, regs.rax as i64, types, &read_process);
+        println!();
+        return;
+    }
+
+    let mut sse = [true, true];
+    classify_eightbytes(type_name, 0, types, &mut sse);
+
+    // copy each eightbyte out of the next register of its class
+    let mut value: [u8; 16] = [0; 16];
+    let int_regs = [regs.rax, regs.rdx];
+    let (mut next_int, mut next_sse) = (0, 0);
+    for i in 0..(size as usize).div_ceil(8).min(2) {
+        if sse[i] {
+            value[i * 8..i * 8 + 8].copy_from_slice(&xmm[next_sse * 16..next_sse * 16 + 8]);
+            next_sse += 1;
+        } else {
+            value[i * 8..i * 8 + 8].copy_from_slice(&int_regs[next_int].to_le_bytes());
+            next_int += 1;
+        }
+    }
+
+    let read_value = |buffer: *mut libc::c_void, address: u64, size: usize| {
+        let start = (address as usize).min(value.len());
+        let end = (start + size).min(value.len());
+        std::ptr::copy_nonoverlapping(value[start..end].as_ptr(), buffer as *mut u8, end - start);
+    };
+    print_struct("", &varname, type_name, 0, types, &read_value);
+    println!();
+}
+
+
+// this function decides what to do when the target process is suspended in
+// the middle of a step
+// returns the action to perform if the step isn't done yet, or None if control
+// should go back to the user
+unsafe fn continue_step(session: &mut Session, rip: u64, reason: libc::c_int) -> Option<libc::c_int> {
+    let step = match session.step.take() {
+        Some(r) => r,
+        None => { return None; }
+    };
+
+    let mut regs = Registers::default();
+    if (reason != STOP_STEP && reason != STOP_BREAKPOINT) || get_registers(&mut regs) == -1 {
+        clear_temporary_breakpoints();
+        return None;
+    }
+
+    match step {
+        Step::Instructions(count) => {
+            if reason == STOP_STEP && count > 1 {
+                session.step = Some(Step::Instructions(count - 1));
+                return Some(ACTION_STEP);
+            }
+        },
+        Step::IntoCall { step_in } => {
+            // if we know about the function that was just called, stop inside it once
+            // its stack frame has been set up
+            if let Some(function) = find_function_at(&session.scope, rip) {
+                if step_in && reason == STOP_STEP && function.low_pc == rip {
+                    let address = skip_prologue(&session.lines, function);
+                    if address == rip { return None; }
+                    if set_temporary_breakpoint(address as libc::uintptr_t) != -1 {
+                        return Some(ACTION_CONTINUE);
+                    }
+                }
+            }
+
+            // the call has pushed the return address onto the stack
+            let mut ret: u64 = 0;
+            read_addr(&mut ret as *mut u64 as *mut libc::c_void, regs.rsp as libc::uintptr_t, 8);
+            if reason == STOP_STEP && set_temporary_breakpoint(ret as libc::uintptr_t) != -1 {
+                let mut count: libc::c_int = 0;
+                let tid = get_thread_id(&mut count);
+                session.step = Some(Step::OverCall { ret, sp: regs.rsp + 8, tid });
+                return Some(ACTION_CONTINUE);
+            }
+        },
+        Step::OverCall { ret, sp, tid } => {
+            if returned_in_other_thread(rip, ret, tid) || returned_from_recursion(rip, &regs, ret, sp) {
+                session.step = Some(Step::OverCall { ret, sp, tid });
+                return Some(ACTION_CONTINUE);
+            }
+        },
+        Step::Finish { ret, sp, tid, function, type_name } => {
+            if returned_in_other_thread(rip, ret, tid) || returned_from_recursion(rip, &regs, ret, sp) {
+                session.step = Some(Step::Finish { ret, sp, tid, function, type_name });
+                return Some(ACTION_CONTINUE);
+            }
+
+            if rip == ret && reason == STOP_STEP {
+                session.finished = Some((function, type_name));
+            }
+        }
+    }
+
+    clear_temporary_breakpoints();
+    None
+}
+
+
+// this function decides what to do when the target process is suspended in the
+// middle of a line step, once any instruction-level step is done
+// returns the action to perform if the line step isn't done yet, or None if
+// control should go back to the user
+unsafe fn continue_line_step(session: &mut Session, rip: u64, reason: libc::c_int) -> Option<libc::c_int> {
+    let line_step = match session.line_step.take() {
+        Some(r) => r,
+        None => { return None; }
+    };
+
+    if reason != STOP_STEP {
+        clear_temporary_breakpoints();
+        return None;
+    }
+
+    // if there is no line information here, we've left the program's code (e.g
+    // returned from main), so there are no more lines to step to
+    let row = match find_line(&session.lines, rip) {
+        Some(r) => r,
+        None => { return Some(ACTION_CONTINUE); }
+    };
+
+    if row.address == rip && row.is_stmt && (row.line != line_step.line || row.file != line_step.file) {
+        return None;
+    }
+
+    session.step = Some(if is_call_instruction(rip) {
+        Step::IntoCall { step_in: !line_step.over_calls }
+    } else {
+        Step::Instructions(1)
+    });
+    session.line_step = Some(line_step);
+
+    Some(ACTION_STEP)
+}
+
+
+// this function tries to find which scope we're inside of in the suspended
+// target process based on the instruction pointer and the low_pc and high_pc
+// attributes of the scope structs constructed earlier
+// takes O(logn) time because the scope struct is essentially a
+// searchable BTree -- CS225 ftw :)
+fn construct_context(
+    scope: &Scope,
+    variables: &mut HashMap<String, Variable>,
+    scopes: &mut Vec<String>,
+    rip: u64
+) {
+    if let Some(ref name) = scope.name {
+        scopes.push(name.clone());
+    } else {
+        scopes.push(String::from("unnamed scope"));
+    }
+
+    for (name, val) in &(scope.variables) {
+        variables.insert(name.clone(), val.clone());
+    }
+
+    for child in &(scope.scopes) {
+        if rip >= child.low_pc && rip - child.low_pc <= child.high_pc {
+            construct_context(child, variables, scopes, rip);
+        }
+    }
+}
+
+
+// this macro reads and prints a variable at a specific address with a reader
+macro_rules! print_result_as {
+    ($read:ident, $t:ty, $addr:ident) => {
+        {
+            let size = std::mem::size_of::<$t>();
+            let result: *mut $t = libc::malloc(size) as *mut $t;
+            $read(result as *mut libc::c_void, $addr as u64, size);
+            println!("{}", *result);
+            libc::free(result as *mut libc::c_void);
+        }
+    };
+
+    ($read:ident, $t:ty, $addr:ident, $hex:ident) => {
+        {
+            let size = std::mem::size_of::<$t>();
+            let result: *mut $t = libc::malloc(size) as *mut $t;
+            $read(result as *mut libc::c_void, $addr as u64, size);
+            println!("{:#x}", *result);
+            libc::free(result as *mut libc::c_void);
+        }
+    };
+
+    ($read:ident, $t:ty, $addr:ident, $count:expr, $zero:expr) => {
+        {
+            let size = std::mem::size_of::<$t>() * $count;
+            let mut result: Vec<$t> = vec![$zero; $count];
+            {
+                let slice: &mut [$t] = &mut result;
+                $read(slice.as_mut_ptr() as *mut libc::c_void, $addr as u64, size);
+            }
+            println!("{:?}", result);
+        }
+    };
+}
+
+
+// this macro resolves the (base) type of a variable and prints it
+macro_rules! print_base_type {
+    ($read:ident, $type_name:ident, $addr:ident, $count:expr) => {
+        match $type_name {
+            "char" | "signed char" | "unsigned char" => {
+                if $count == 1 { print_result_as!($read, libc::c_char, $addr); }
+                else { print_result_as!($read, libc::c_char, $addr, ($count), 0); }
+            },
+
+            "short" | "signed short" | "short int" | "signed short int" | "short signed" | "short signed int" => {
+                if $count == 1 { print_result_as!($read, i16, $addr); }
+                else { print_result_as!($read, i16, $addr, ($count), 0); }
+            },
+            "unsigned short" | "unsigned short int" | "short unsigned" | "short unsigned int" => {
+                if $count == 1 { print_result_as!($read, u16, $addr); }
+                else { print_result_as!($read, u16, $addr, ($count), 0); }
+            },
+
+            "int" | "signed int" | "signed" => {
+                if $count == 1 { print_result_as!($read, i32, $addr); }
+                else { print_result_as!($read, i32, $addr, ($count), 0); }
+            },
+            "unsigned int" | "unsigned" => {
+                if $count == 1 { print_result_as!($read, u32, $addr); }
+                else { print_result_as!($read, u32, $addr, ($count), 0); }
+            },
+
+            "long" | "signed long" | "long int" | "signed long int" | "long signed" | "long signed int" => {
+                if $count == 1 { print_result_as!($read, i64, $addr); }
+                else { print_result_as!($read, i64, $addr, ($count), 0); }
+            },
+            "unsigned long" | "unsigned long int" | "long unsigned" | "long unsigned int" => {
+                if $count == 1 { print_result_as!($read, u64, $addr); }
+                else { print_result_as!($read, u64, $addr, ($count), 0); }
+            },
+
+            "long long" | "signed long long" | "long long int" | "signed long long int" | "long long signed" | "long long signed int" => {
+                if $count == 1 { print_result_as!($read, i64, $addr); }
+                else { print_result_as!($read, i64, $addr, ($count), 0); }
+            },
+            "unsigned long long" | "unsigned long long int" | "long long unsigned" | "long long unsigned int" => {
+                if $count == 1 { print_result_as!($read, u64, $addr); }
+                else { print_result_as!($read, u64, $addr, ($count), 0); }
+            },
+
+            "float" => {
+                if $count == 1 { print_result_as!($read, f32, $addr); }
+                else { print_result_as!($read, f32, $addr, ($count), 0.0); }
+            },
+            "double" => {
+                if $count == 1 { print_result_as!($read, f64, $addr); }
+                else { print_result_as!($read, f64, $addr, ($count), 0.0); }
+            }
+
+            "*" => {
+                if $count == 1 { print_result_as!($read, u64, $addr, $addr); }
+                else { print_result_as!($read, u64, $addr, ($count), 0); }
+            }
+
+            _ => { println!("unknown type"); }
+        }
+    };
+}
+
+
+// a reader copies `size` bytes at an address into a buffer. values are usually
+// read from the target process's memory, but some (e.g return values) live in
+// registers instead
+type Reader<'a> = &'a dyn Fn(*mut libc::c_void, u64, usize);
+
+
+// this function reads memory in the target process -- it is the reader that
+// values are usually printed with
+fn read_process(buffer: *mut libc::c_void, address: u64, size: usize) {
+    unsafe { read_addr(buffer, address as libc::uintptr_t, size); }
+}
+
+
+// this function recursively resolves the (derived) type of a variable and prints it
+fn print_struct(offset: &str, varname: &str, type_name: &str, addr: i64, types: &HashMap<String, DerivedType>, read: Reader) {
+    print!("{}{} {}: ", offset, type_name, varname);
+    let d_type = types.get(type_name);
+    if let Some(ref dt) = d_type {
+        println!("");
+        let new_offset = format!("  {}", offset);
+        if dt.members.len() > 0 {
+            for member in &dt.members {
+                let new_addr = addr + member.offset;
+                print_struct(&new_offset, &member.name, &member.type_name, new_addr, types, read);
+            }
+        } else {
+            print_struct(&new_offset, varname, &dt.base_type, addr, types, read);
+        }
+    } else {
+        unsafe { print_base_type!(read, type_name, addr, 1); }
+    }
+}
+
+
+// this function reads an arbitrary address in the child process as a specific type and prints the results
+unsafe fn read_ptr(address: u64, count: usize, type_name: &str, types: &HashMap<String, DerivedType>) {
+    let d_type = types.get(type_name);
+
+    if d_type.is_none() {
+        print_base_type!(read_process, type_name, address, (count));
+    } else {
+        if d_type.unwrap().members.len() > 0 {
+            println!("cannot read structs yet"); return;
+        }
+        read_ptr(address, count, &d_type.unwrap().base_type, types);
+    }
+}
+
+
+// this function describes a kind of watchpoint, e.g "Hardware read watchpoint"
+fn watchpoint_label(watchpoint: &Watchpoint) -> &'static str {
+    if watchpoint.software { return "Software watchpoint"; }
+    match watchpoint.kind {
+        WATCH_READ => "Hardware read watchpoint",
+        WATCH_ACCESS => "Hardware access (read/write) watchpoint",
+        _ => "Hardware watchpoint"
+    }
+}
+
+
+// this function shows the value of a watched expression that has just been
+// accessed. `new` is its current value, and if it differs from the old one
+// both are shown
+fn print_watchpoint_value(watchpoint: &Watchpoint, new: &Vec<u8>, types: &HashMap<String, DerivedType>) {
+    println!("{} {}: {}\n", watchpoint_label(watchpoint), watchpoint.number, watchpoint.name);
+
+    // the old value isn't in memory anymore, so it is read from our copy
+    let old = &watchpoint.old;
+    let start = watchpoint.address;
+    let read_old = |buffer: *mut libc::c_void, address: u64, size: usize| {
+        if address >= start && address + size as u64 <= start + old.len() as u64 {
+            let from = (address - start) as usize;
+            unsafe { std::ptr::copy_nonoverlapping(old[from..].as_ptr(), buffer as *mut u8, size); }
+        } else {
+            read_process(buffer, address, size);
+        }
+    };
+
+    if new != old {
+        println!("Old value:");
+        print_struct("  ", &watchpoint.name, &watchpoint.type_name, start as i64, types, &read_old);
+        println!("New value:");
+    } else {
+        println!("Value:");
+    }
+    print_struct("  ", &watchpoint.name, &watchpoint.type_name, start as i64, types, &read_process);
+    println!();
+}
+
+
+// this function reports a hardware watchpoint that has fired
+// a write that doesn't change the value isn't worth stopping for, and x86
+// can't watch only reads, so read watchpoints also fire on writes. returns
+// false in both cases, and the process shouldn't stop
+unsafe fn report_watchpoint(watchpoints: &mut [Watchpoint], number: libc::c_int, types: &HashMap<String, DerivedType>) -> bool {
+    let watchpoint = match watchpoints.iter_mut().find(|w| w.number == number) {
+        Some(r) => r,
+        None => { return true; }
+    };
+
+    let mut new = vec![0u8; watchpoint.old.len()];
+    read_addr(new.as_mut_ptr() as *mut libc::c_void, watchpoint.address as libc::uintptr_t, new.len());
+    let changed = new != watchpoint.old;
+    if (watchpoint.kind == WATCH_READ && changed) || (watchpoint.kind == WATCH_WRITE && !changed) {
+        watchpoint.old = new;
+        return false;
+    }
+
+    print_watchpoint_value(watchpoint, &new, types);
+    watchpoint.old = new;
+    true
+}
+
+
+// this function checks whether the program has left the block that a watchpoint
+// on a local variable lives in: either its function has returned (the saved RBP
+// and return address above the frame have been popped), or the program is still
+// in that frame but outside of the block
+fn left_block(watchpoint: &Watchpoint, rip: u64, regs: &Registers) -> bool {
+    let (low_pc, high_pc) = match watchpoint.block {
+        Some(r) => r,
+        None => { return false; }
+    };
+
+    let in_block = rip >= low_pc && rip - low_pc < high_pc;
+    let same_frame = regs.rbp == watchpoint.frame && regs.rsp >= watchpoint.sp;
+    regs.rsp > watchpoint.frame + 8 || (same_frame && !in_block)
+}
+
+
+// this function deletes the hardware watchpoints on local variables whose block
+// the program has left, along with their scope breakpoints
+// returns true if any were deleted
+unsafe fn check_watchpoint_scopes(session: &mut Session, rip: u64) -> bool {
+    let mut regs = Registers::default();
+    if get_registers(&mut regs) == -1 { return false; }
+
+    let mut deleted: Vec<libc::c_int> = Vec::new();
+    for watchpoint in session.watchpoints.iter().filter(|w| !w.software) {
+        if left_block(watchpoint, rip, &regs) {
+            println!("{} {} deleted because the program has left the block", watchpoint_label(watchpoint), watchpoint.number);
+            println!("in which '{}' is valid.\n", watchpoint.name);
+            deleted.push(watchpoint.number);
+        }
+    }
+
+    for number in &deleted {
+        delete_numbered(&mut session.breakpoints, &mut session.watchpoints, &mut session.catchpoints, *number);
+    }
+    !deleted.is_empty()
+}
+
+
+// this function compares the software watchpoints with their old values, and
+// reports the ones that changed. a watchpoint on a local variable is deleted
+// once the program leaves the block it lives in
+// returns true if the process should stop
+unsafe fn check_software_watchpoints(session: &mut Session, rip: u64) -> bool {
+    let mut regs = Registers::default();
+    if get_registers(&mut regs) == -1 { return false; }
+
+    let types = &session.types;
+    let mut stop = false;
+    let mut deleted: Vec<libc::c_int> = Vec::new();
+    for watchpoint in session.watchpoints.iter_mut().filter(|w| w.software) {
+        if left_block(watchpoint, rip, &regs) {
+            println!("Software watchpoint {} deleted because the program has left the block", watchpoint.number);
+            println!("in which '{}' is valid.\n", watchpoint.name);
+            deleted.push(watchpoint.number);
+            stop = true;
+            continue;
+        }
+
+        let label = watchpoint_label(watchpoint);
+        if let Some(ref mut computed) = watchpoint.computed {
+            // an expression that can't be evaluated anymore stops the process,
+            // so that the user can delete it
+            let new = match evaluate_computed(&watchpoint.name, computed, types) {
+                Ok(r) => r,
+                Err(err) => {
+                    println!("Error evaluating software watchpoint {}: {}\n", watchpoint.number, err);
+                    stop = true;
+                    continue;
+                }
+            };
+            if new != computed.value {
+                println!("{} {}: {}\n", label, watchpoint.number, watchpoint.name);
+                println!("Old value = {}", computed.value);
+                println!("New value = {}\n", new);
+                computed.value = new;
+                stop = true;
+            }
+            continue;
+        }
+
+        let mut new = vec![0u8; watchpoint.old.len()];
+        read_addr(new.as_mut_ptr() as *mut libc::c_void, watchpoint.address as libc::uintptr_t, new.len());
+        if new != watchpoint.old {
+            print_watchpoint_value(watchpoint, &new, types);
+            watchpoint.old = new;
+            stop = true;
+        }
+    }
+
+    session.watchpoints.retain(|w| !deleted.contains(&w.number));
+    if !session.watchpoints.iter().any(|w| w.software) {
+        set_watch_stepping(0);
+    }
+
+    stop
+}
+
+
+// this function evaluates a computed watch expression in the frame that it was
+// set in
+fn evaluate_computed(expression: &str, computed: &Computed, types: &HashMap<String, DerivedType>) -> Result<Value, String> {
+    let lookup = |name: &str| {
+        let (address, type_name) = resolve_expression(name, &computed.variables, types, computed.base)?;
+        read_value(address, &type_name, types)
+    };
+    evaluate_expression(expression, &lookup)
+}
+
+
+// this function finds the address and type of a watch expression: a variable,
+// optionally followed by struct members, e.g `point.x`
+// `base` is the address that variable offsets are relative to
+fn resolve_expression(
+    expression: &str,
+    variables: &HashMap<String, Variable>,
+    types: &HashMap<String, DerivedType>,
+    base: i64
+) -> Result<(u64, String), String> {
+    let mut parts = expression.split('.');
+    let varname = parts.next().unwrap_or("");
+    let variable = match variables.get(varname) {
+        Some(r) => r,
+        None => { return Err(format!("unrecognized variable '{}'.", varname)); }
+    };
+
+    let mut address = base + variable.offset;
+    let mut type_name = variable.type_name.clone();
+    for member_name in parts {
+        // typedefs have to be resolved to the struct they name
+        let mut dt = types.get(&type_name);
+        while let Some(t) = dt {
+            if !t.members.is_empty() { break; }
+            dt = types.get(&t.base_type);
+        }
+
+        let member = match dt.and_then(|t| t.members.iter().find(|m| m.name == member_name)) {
+            Some(r) => r,
+            None => { return Err(format!("'{}' has no member named '{}'.", type_name, member_name)); }
+        };
+        address += member.offset;
+        type_name = member.type_name.clone();
+    }
+
+    Ok((address as u64, type_name))
+}
+
+
+// A value that a condition works with. Integers and floating point numbers are
+// compared and combined the way C would
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Value {
+    Int(i64),
+    Float(f64)
+}
+
+
+impl Value {
+    fn as_f64(self) -> f64 {
+        match self {
+            Value::Int(i) => i as f64,
+            Value::Float(f) => f
+        }
+    }
+
+    fn is_true(self) -> bool {
+        match self {
+            Value::Int(i) => i != 0,
+            Value::Float(f) => f != 0.0
+        }
+    }
+}
+
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x)
+        }
+    }
+}
+
+
+// this function reads a value of type `type_name` at `address` in the target
+// process so that a condition can use it. only numbers and pointers can be
+fn read_value(address: u64, type_name: &str, types: &HashMap<String, DerivedType>) -> Result<Value, String> {
+    if let Some(dt) = types.get(type_name) {
+        if !dt.members.is_empty() {
+            return Err(format!("values of type '{}' can't be used in a condition.", type_name));
+        }
+        return read_value(address, &dt.base_type, types);
+    }
+
+    macro_rules! read_as {
+        ($t:ty) => {
+            {
+                let mut result: $t = Default::default();
+                read_process(&mut result as *mut $t as *mut libc::c_void, address, std::mem::size_of::<$t>());
+                result
+            }
+        };
+    }
+
+    Ok(match type_name {
+        "char" | "signed char" => Value::Int(read_as!(i8) as i64),
+        "unsigned char" | "_Bool" => Value::Int(read_as!(u8) as i64),
+        "short" | "signed short" | "short int" | "signed short int" | "short signed" | "short signed int" => Value::Int(read_as!(i16) as i64),
+        "unsigned short" | "unsigned short int" | "short unsigned" | "short unsigned int" => Value::Int(read_as!(u16) as i64),
+        "int" | "signed int" | "signed" => Value::Int(read_as!(i32) as i64),
+        "unsigned int" | "unsigned" => Value::Int(read_as!(u32) as i64),
+        "long" | "signed long" | "long int" | "signed long int" | "long signed" | "long signed int" |
+        "long long" | "signed long long" | "long long int" | "signed long long int" | "long long signed" | "long long signed int" |
+        "unsigned long" | "unsigned long int" | "long unsigned" | "long unsigned int" |
+        "unsigned long long" | "unsigned long long int" | "long long unsigned" | "long long unsigned int" | "*" => Value::Int(read_as!(i64)),
+        "float" => Value::Float(read_as!(f32) as f64),
+        "double" => Value::Float(read_as!(f64)),
+        _ => { return Err(format!("values of type '{}' can't be used in a condition.", type_name)); }
+    })
+}
+
+
+// this function splits a condition into tokens: numbers, variables (optionally
+// followed by struct members), parentheses and operators
+fn tokenize_condition(condition: &str) -> Result<Vec<String>, String> {
+    const OPERATORS: &[&str] = &["||", "&&", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "*", "/", "%", "(", ")"];
+
+    let mut tokens: Vec<String> = Vec::new();
+    let chars: Vec<char> = condition.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => {
+                    tokens.push(op.to_string());
+                    i += op.len();
+                },
+                None => { return Err(format!("unexpected '{}' in condition.", c)); }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+
+// this struct evaluates a condition as it parses it, from the lowest
+// precedence operator (||) to the highest (unary operators). `lookup` finds
+// the value of a variable
+struct ConditionParser<'a> {
+    tokens: Vec<String>,
+    position: usize,
+    lookup: &'a dyn Fn(&str) -> Result<Value, String>
+}
+
+
+impl<'a> ConditionParser<'a> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Value, String> {
+        let mut value = self.and()?;
+        while self.peek() == Some("||") {
+            self.next();
+            let right = self.and()?;
+            value = Value::Int((value.is_true() || right.is_true()) as i64);
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<Value, String> {
+        let mut value = self.comparison()?;
+        while self.peek() == Some("&&") {
+            self.next();
+            let right = self.comparison()?;
+            value = Value::Int((value.is_true() && right.is_true()) as i64);
+        }
+        Ok(value)
+    }
+
+    fn comparison(&mut self) -> Result<Value, String> {
+        let mut value = self.sum()?;
+        while let Some(op) = self.peek().map(String::from) {
+            if !["==", "!=", "<", "<=", ">", ">="].contains(&op.as_str()) { break; }
+            self.next();
+            let right = self.sum()?;
+            let ordering = match (value, right) {
+                (Value::Int(l), Value::Int(r)) => l.partial_cmp(&r),
+                (l, r) => l.as_f64().partial_cmp(&r.as_f64())
+            };
+            let result = match (op.as_str(), ordering) {
+                (_, None) => op == "!=",
+                ("==", Some(o)) => o == std::cmp::Ordering::Equal,
+                ("!=", Some(o)) => o != std::cmp::Ordering::Equal,
+                ("<", Some(o)) => o == std::cmp::Ordering::Less,
+                ("<=", Some(o)) => o != std::cmp::Ordering::Greater,
+                (">", Some(o)) => o == std::cmp::Ordering::Greater,
+                (_, Some(o)) => o != std::cmp::Ordering::Less
+            };
+            value = Value::Int(result as i64);
+        }
+        Ok(value)
+    }
+
+    fn sum(&mut self) -> Result<Value, String> {
+        let mut value = self.product()?;
+        while let Some(op) = self.peek().map(String::from) {
+            if op != "+" && op != "-" { break; }
+            self.next();
+            let right = self.product()?;
+            value = match (value, right, op.as_str()) {
+                (Value::Int(l), Value::Int(r), "+") => Value::Int(l.wrapping_add(r)),
+                (Value::Int(l), Value::Int(r), _) => Value::Int(l.wrapping_sub(r)),
+                (l, r, "+") => Value::Float(l.as_f64() + r.as_f64()),
+                (l, r, _) => Value::Float(l.as_f64() - r.as_f64())
+            };
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<Value, String> {
+        let mut value = self.unary()?;
+        while let Some(op) = self.peek().map(String::from) {
+            if op != "*" && op != "/" && op != "%" { break; }
+            self.next();
+            let right = self.unary()?;
+            value = match (value, right, op.as_str()) {
+                (Value::Int(_), Value::Int(0), _) => { return Err(String::from("division by zero.")); },
+                (Value::Int(l), Value::Int(r), "*") => Value::Int(l.wrapping_mul(r)),
+                (Value::Int(l), Value::Int(r), "/") => Value::Int(l.wrapping_div(r)),
+                (Value::Int(l), Value::Int(r), _) => Value::Int(l.wrapping_rem(r)),
+                (l, r, "*") => Value::Float(l.as_f64() * r.as_f64()),
+                (l, r, "/") => Value::Float(l.as_f64() / r.as_f64()),
+                (_, _, _) => { return Err(String::from("'%' only works on integers.")); }
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some("!") => {
+                self.next();
+                let value = self.unary()?;
+                Ok(Value::Int(!value.is_true() as i64))
+            },
+            Some("-") => {
+                self.next();
+                Ok(match self.unary()? {
+                    Value::Int(i) => Value::Int(i.wrapping_neg()),
+                    Value::Float(f) => Value::Float(-f)
+                })
+            },
+            _ => self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Value, String> {
+        let token = match self.next() {
+            Some(r) => r,
+            None => { return Err(String::from("condition ended unexpectedly.")); }
+        };
+
+        if token == "(" {
+            let value = self.or()?;
+            if self.next().as_deref() != Some(")") {
+                return Err(String::from("missing ')' in condition."));
+            }
+            return Ok(value);
+        }
+
+        let first = token.chars().next().unwrap();
+        if first.is_ascii_digit() {
+            if let Some(hex) = token.strip_prefix("0x") {
+                return i64::from_str_radix(hex, 16).map(Value::Int)
+                    .map_err(|_| format!("invalid number '{}'.", token));
+            }
+            if let Ok(i) = token.parse::<i64>() {
+                return Ok(Value::Int(i));
+            }
+            return token.parse::<f64>().map(Value::Float)
+                .map_err(|_| format!("invalid number '{}'.", token));
+        }
+        if first.is_alphabetic() || first == '_' {
+            return (self.lookup)(&token);
+        }
+
+        Err(format!("unexpected '{}' in condition.", token))
+    }
+}
+
+
+// this function evaluates `expression` with `lookup` finding the values of
+// the variables in it
+fn evaluate_expression(expression: &str, lookup: &dyn Fn(&str) -> Result<Value, String>) -> Result<Value, String> {
+    let mut parser = ConditionParser { tokens: tokenize_condition(expression)?, position: 0, lookup };
+    let value = parser.or()?;
+    if let Some(token) = parser.peek() {
+        return Err(format!("unexpected '{}' in '{}'.", token, expression));
+    }
+
+    Ok(value)
+}
+
+
+// this function evaluates `condition` with `lookup` finding the values of the
+// variables in it
+fn evaluate_condition(condition: &str, lookup: &dyn Fn(&str) -> Result<Value, String>) -> Result<bool, String> {
+    evaluate_expression(condition, lookup).map(|v| v.is_true())
+}
+
+
+// this function checks whether a condition makes sense before a breakpoint
+// is set with it. whether its variables exist can only be known once the
+// breakpoint is hit
+fn check_condition(condition: &str) -> Result<(), String> {
+    evaluate_condition(condition, &|_| Ok(Value::Int(1))).map(|_| ())
+}
+
+
+// this function reads the NUL-terminated string at `address` in the target
+// process, up to `max` bytes of it
+fn read_c_string(address: u64, max: usize) -> String {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; 8];
+    'outer: while bytes.len() < max {
+        read_process(chunk.as_mut_ptr() as *mut libc::c_void, address + bytes.len() as u64, chunk.len());
+        for &byte in chunk.iter() {
+            if byte == 0 || bytes.len() == max { break 'outer; }
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+
+// this function shows `value` the way C's %e does, with `precision` digits
+// after the decimal point and at least two digits in the exponent
+fn format_exponent(value: f64, precision: usize, upper: bool) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+    let exponent: i32 = exponent[1..].parse().unwrap();
+    let sign = if exponent < 0 { '-' } else { '+' };
+    let e = if upper { 'E' } else { 'e' };
+
+    format!("{}{}{}{:02}", mantissa, e, sign, exponent.abs())
+}
+
+
+// this function formats `values` the way C's printf would format them with
+// `format`. it understands flags, field widths, precisions and the usual
+// conversions. %s takes the address of a string in the target process
+fn format_printf(format: &str, values: &[Value]) -> Result<String, String> {
+    let mut output = String::new();
+    let mut values = values.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            output.push('%');
+            continue;
+        }
+
+        let mut flags = String::new();
+        while let Some(&f) = chars.peek() {
+            if !"-+ #0".contains(f) { break; }
+            flags.push(f);
+            chars.next();
+        }
+        let mut width = String::new();
+        while let Some(&d) = chars.peek() {
+            if !d.is_ascii_digit() { break; }
+            width.push(d);
+            chars.next();
+        }
+        let mut precision: Option<usize> = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek() {
+                if !d.is_ascii_digit() { break; }
+                digits.push(d);
+                chars.next();
+            }
+            precision = Some(digits.parse().unwrap_or(0));
+        }
+        // values are read with their real size already, so the length only
+        // matters for how many bits of an integer are shown
+        let mut long = false;
+        while let Some(&l) = chars.peek() {
+            if !"hlqjztL".contains(l) { break; }
+            if "lqjzt".contains(l) { long = true; }
+            chars.next();
+        }
+
+        let conversion = match chars.next() {
+            Some(r) => r,
+            None => { return Err(String::from("format ends in the middle of a conversion.")); }
+        };
+        let value = match values.next() {
+            Some(r) => *r,
+            None => { return Err(String::from("not enough arguments for the format.")); }
+        };
+        let int = match value {
+            Value::Int(i) => if long { i } else { i as i32 as i64 },
+            Value::Float(f) => f as i64
+        };
+        let unsigned = if long { int as u64 } else { int as u32 as u64 };
+        let alternate = flags.contains('#');
+
+        let mut text = match conversion {
+            'd' | 'i' => {
+                let digits = format!("{:01$}", int.unsigned_abs(), precision.unwrap_or(1));
+                let sign = if int < 0 { "-" } else if flags.contains('+') { "+" } else if flags.contains(' ') { " " } else { "" };
+                format!("{}{}", sign, digits)
+            },
+            'u' => format!("{:01$}", unsigned, precision.unwrap_or(1)),
+            'x' => format!("{}{:02$x}", if alternate && unsigned != 0 { "0x" } else { "" }, unsigned, precision.unwrap_or(1)),
+            'X' => format!("{}{:02$X}", if alternate && unsigned != 0 { "0X" } else { "" }, unsigned, precision.unwrap_or(1)),
+            'o' => format!("{}{:02$o}", if alternate { "0" } else { "" }, unsigned, precision.unwrap_or(1)),
+            'p' => format!("{:#x}", int as u64),
+            'c' => ((int as u8) as char).to_string(),
+            's' => {
+                let text = read_c_string(int as u64, 4096);
+                match precision {
+                    Some(p) => text.chars().take(p).collect(),
+                    None => text
+                }
+            },
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                let float = value.as_f64();
+                let precision = precision.unwrap_or(6);
+                let mut text = match conversion {
+                    'f' | 'F' => format!("{:.*}", precision, float),
+                    'e' | 'E' => format_exponent(float, precision, conversion == 'E'),
+                    _ => {
+                        // %g picks %e or %f depending on the exponent, and drops
+                        // trailing zeros
+                        let precision = std::cmp::max(precision, 1);
+                        let exponent = if float == 0.0 { 0 } else { float.abs().log10().floor() as i32 };
+                        let mut text = if exponent < -4 || exponent >= precision as i32 {
+                            format_exponent(float, precision - 1, conversion == 'G')
+                        } else {
+                            format!("{:.*}", (precision as i32 - 1 - exponent) as usize, float)
+                        };
+                        if !alternate && text.contains('.') {
+                            let e = text.find(['e', 'E']).unwrap_or(text.len());
+                            let mantissa = text[..e].trim_end_matches('0').trim_end_matches('.').to_string();
+                            text = format!("{}{}", mantissa, &text[e..]);
+                        }
+                        text
+                    }
+                };
+                if float >= 0.0 && flags.contains('+') {
+                    text.insert(0, '+');
+                } else if float >= 0.0 && flags.contains(' ') {
+                    text.insert(0, ' ');
+                }
+                text
+            },
+            other => { return Err(format!("unsupported conversion '%{}'.", other)); }
+        };
+
+        let width: usize = width.parse().unwrap_or(0);
+        let length = text.chars().count();
+        if length < width {
+            let padding = width - length;
+            if flags.contains('-') {
+                text.push_str(&" ".repeat(padding));
+            } else if flags.contains('0') && !"sc".contains(conversion) && precision.is_none() {
+                // zeros go after the sign and the 0x prefix
+                let mut prefix = if text.starts_with(['-', '+', ' ']) { 1 } else { 0 };
+                if text[prefix..].starts_with("0x") || text[prefix..].starts_with("0X") { prefix += 2; }
+                text.insert_str(prefix, &"0".repeat(padding));
+            } else {
+                text.insert_str(0, &" ".repeat(padding));
+            }
+        }
+        output.push_str(&text);
+    }
+
+    if values.next().is_some() {
+        return Err(String::from("too many arguments for the format."));
+    }
+
+    Ok(output)
+}
+
+
+// this function splits the arguments of 'dprintf', which look like
+// `<location>,"<format>",<arg>,<arg>...`, into the location, the format (with
+// its escape sequences turned into the characters they stand for) and the
+// arguments
+fn parse_dprintf(text: &str) -> Result<(String, String, Vec<String>), String> {
+    let comma = match text.find(',') {
+        Some(r) => r,
+        None => { return Err(String::from("expected a ',' after the location.")); }
+    };
+    let location = text[..comma].trim().to_string();
+    let rest = text[comma + 1..].trim_start();
+    if !rest.starts_with('"') {
+        return Err(String::from("the format has to be a string in double quotes."));
+    }
+
+    let mut format = String::new();
+    let mut chars = rest[1..].char_indices();
+    let mut end = None;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => { end = Some(i + 2); break; },
+            '\\' => {
+                format.push(match chars.next().map(|(_, e)| e) {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some('a') => '\x07',
+                    Some(e) => e,
+                    None => { return Err(String::from("the format ends with a '\\'.")); }
+                });
+            },
+            _ => { format.push(c); }
+        }
+    }
+    let end = match end {
+        Some(r) => r,
+        None => { return Err(String::from("the format is missing its closing '\"'.")); }
+    };
+
+    let rest = rest[end..].trim();
+    let mut args = Vec::new();
+    if !rest.is_empty() {
+        if !rest.starts_with(',') {
+            return Err(String::from("expected a ',' after the format."));
+        }
+        for arg in rest[1..].split(',') {
+            let arg = arg.trim();
+            if arg.is_empty() {
+                return Err(String::from("empty argument."));
+            }
+            check_condition(arg)?;
+            args.push(arg.to_string());
+        }
+    }
+
+    Ok((location, format, args))
+}
+
+
+// this function decides whether breakpoint `number`, which the process has
+// just hit, should stop it. a false condition or an ignore count let the
+// process carry on. `base` is the address that variable offsets are
+// relative to
+unsafe fn breakpoint_stops(session: &mut Session, number: libc::c_int, rip: u64, base: i64) -> bool {
+    let mut variables: HashMap<String, Variable> = HashMap::new();
+    let mut scopes: Vec<String> = Vec::new();
+    construct_context(&session.scope, &mut variables, &mut scopes, rip);
+
+    let types = &session.types;
+    let breakpoint = match session.breakpoints.iter_mut().find(|b| b.number == number) {
+        Some(r) => r,
+        None => { return true; }
+    };
+
+    let lookup = |name: &str| {
+        let (address, type_name) = resolve_expression(name, &variables, types, base)?;
+        read_value(address, &type_name, types)
+    };
+    if let Some(ref condition) = breakpoint.condition {
+        match evaluate_condition(condition, &lookup) {
+            Ok(true) => {},
+            Ok(false) => { return false; },
+            Err(err) => {
+                // a condition that can't be evaluated stops the process, so
+                // that the user can fix it
+                println!("Error in testing the condition of breakpoint {}: {}", number, err);
+            }
+        }
+    }
+
+    breakpoint.hits += 1;
+    if breakpoint.ignore > 0 {
+        breakpoint.ignore -= 1;
+        return false;
+    }
+
+    // a dprintf prints its message instead of stopping
+    if let Some(ref dprintf) = breakpoint.dprintf {
+        let mut values = Vec::new();
+        for arg in &dprintf.args {
+            match evaluate_expression(arg, &lookup) {
+                Ok(r) => { values.push(r); },
+                Err(err) => {
+                    println!("Error in evaluating the arguments of dprintf {}: {}", number, err);
+                    return true;
+                }
+            }
+        }
+        match format_printf(&dprintf.format, &values) {
+            Ok(r) => { print!("{}", r); },
+            Err(err) => {
+                println!("Error in formatting dprintf {}: {}", number, err);
+                return true;
+            }
+        }
+        std::io::stdout().flush().unwrap();
+        return false;
+    }
+
+    true
+}
+
+
+// this function turns stopping at system calls on or off, depending on
+// whether anything still wants to see them
+unsafe fn update_syscall_tracing(session: &Session) {
+    let on = session.trace_syscalls || session.catchpoints.iter().any(|c| c.enabled);
+    set_syscall_tracing(on as libc::c_int);
+}
+
+
+// this function lists every breakpoint, watchpoint and catchpoint
+fn print_breakpoints(session: &Session) {
+    // each row is the number, type, whether it is enabled, the address and a
+    // description, followed by more details
+    let mut rows: Vec<(libc::c_int, String, bool, String, String, Vec<String>)> = Vec::new();
+    for breakpoint in &session.breakpoints {
+        let mut details = Vec::new();
+        if let Some(ref condition) = breakpoint.condition {
+            details.push(format!("stop only if {}", condition));
+        }
+        if breakpoint.hits > 0 {
+            let times = if breakpoint.hits == 1 { "time" } else { "times" };
+            details.push(format!("breakpoint already hit {} {}", breakpoint.hits, times));
+        }
+        if breakpoint.ignore > 0 {
+            details.push(format!("will ignore next {} crossings of breakpoint", breakpoint.ignore));
+        }
+        if let Some(ref dprintf) = breakpoint.dprintf {
+            let format = format!("{:?}", dprintf.format);
+            let args: Vec<String> = dprintf.args.iter().map(|a| format!(",{}", a)).collect();
+            details.push(format!("printf {}{}", format, args.concat()));
+        }
+        let kind = if breakpoint.dprintf.is_some() {
+            "dprintf"
+        } else if breakpoint.temporary {
+            "tbreakpoint"
+        } else {
+            "breakpoint"
+        };
+        let address = match breakpoint.pending {
+            Some(_) => String::from("<PENDING>"),
+            None => format!("{:#018x}", breakpoint.address)
+        };
+        rows.push((breakpoint.number, String::from(kind), breakpoint.enabled,
+                   address, breakpoint.location.clone(), details));
+    }
+    for watchpoint in &session.watchpoints {
+        let kind = match (watchpoint.software, watchpoint.kind) {
+            (true, _) => "watchpoint",
+            (false, WATCH_READ) => "read watchpoint",
+            (false, WATCH_ACCESS) => "acc watchpoint",
+            (false, _) => "hw watchpoint"
+        };
+        rows.push((watchpoint.number, String::from(kind), true, String::new(), watchpoint.name.clone(), Vec::new()));
+    }
+    for catchpoint in &session.catchpoints {
+        let what = if catchpoint.syscalls.is_empty() {
+            String::from("syscall \"<any syscall>\"")
+        } else {
+            let names: Vec<String> = catchpoint.syscalls.iter().map(|n| syscall_name(*n as libc::c_int)).collect();
+            format!("syscall \"{}\"", names.join(" "))
+        };
+        rows.push((catchpoint.number, String::from("catchpoint"), catchpoint.enabled, String::new(), what, Vec::new()));
+    }
+
+    if rows.is_empty() {
+        println!("No breakpoints, watchpoints or catchpoints.");
+        return;
+    }
+
+    rows.sort_by_key(|r| r.0);
+    println!("{:<7} {:<15} {:<3} {:<18} What", "Num", "Type", "Enb", "Address");
+    for (number, kind, enabled, address, what, details) in rows {
+        println!("{:<7} {:<15} {:<3} {:<18} {}", number, kind, if enabled { "y" } else { "n" }, address, what);
+        for detail in details {
+            println!("        {}", detail);
+        }
+    }
+}
+
+
+// this function deletes the breakpoint, watchpoint or catchpoint `number`
+// returns false if there is none. whether system calls are still traced has
+// to be updated afterwards
+unsafe fn delete_numbered(
+    breakpoints: &mut Vec<Breakpoint>,
+    watchpoints: &mut Vec<Watchpoint>,
+    catchpoints: &mut Vec<Catchpoint>,
+    number: libc::c_int
+) -> bool {
+    if let Some(index) = breakpoints.iter().position(|b| b.number == number) {
+        let breakpoint = breakpoints.remove(index);
+        if breakpoint.enabled && breakpoint.pending.is_none() { delete_breakpoint(number); }
+        return true;
+    }
+
+    if let Some(index) = watchpoints.iter().position(|w| w.number == number) {
+        let watchpoint = watchpoints.remove(index);
+        if !watchpoint.software {
+            delete_breakpoint(number);
+
+            // other watchpoints in the same frame still need the scope breakpoint
+            if watchpoint.ret != 0 && !watchpoints.iter().any(|w| w.ret == watchpoint.ret) {
+                delete_scope_breakpoint(watchpoint.ret as libc::uintptr_t);
+            }
+        } else if !watchpoints.iter().any(|w| w.software) {
+            set_watch_stepping(0);
+        }
+        return true;
+    }
+
+    if let Some(index) = catchpoints.iter().position(|c| c.number == number) {
+        catchpoints.remove(index);
+        return true;
+    }
+
+    false
+}
+
+
+// this function enables or disables the breakpoint or catchpoint `number`. a
+// disabled breakpoint is taken out of the process and put back once it is
+// enabled again. returns false if there is no such breakpoint
+unsafe fn enable_numbered(
+    breakpoints: &mut [Breakpoint],
+    watchpoints: &[Watchpoint],
+    catchpoints: &mut [Catchpoint],
+    number: libc::c_int,
+    enable: bool
+) -> bool {
+    if let Some(breakpoint) = breakpoints.iter_mut().find(|b| b.number == number) {
+        if breakpoint.enabled != enable {
+            let result = if breakpoint.pending.is_some() {
+                0
+            } else if enable {
+                enable_breakpoint(breakpoint.address as libc::uintptr_t, number)
+            } else {
+                delete_breakpoint(number)
+            };
+            if result != -1 { breakpoint.enabled = enable; }
+        }
+        return true;
+    }
+
+    if watchpoints.iter().any(|w| w.number == number) {
+        println!("watchpoint {} can't be disabled, delete it instead.", number);
+        return true;
+    }
+
+    if let Some(catchpoint) = catchpoints.iter_mut().find(|c| c.number == number) {
+        catchpoint.enabled = enable;
+        return true;
+    }
+
+    false
+}
+
+
+// this function finds the lexical block (or function) that declares the
+// variable `name` visible at `rip`, and returns its address range
+fn find_variable_block(scope: &Scope, name: &str, rip: u64) -> Option<(u64, u64)> {
+    let mut block = None;
+    if scope.variables.contains_key(name) && scope.high_pc != u64::MAX {
+        block = Some((scope.low_pc, scope.high_pc));
+    }
+
+    for child in &(scope.scopes) {
+        if rip >= child.low_pc && rip - child.low_pc < child.high_pc {
+            if let Some(r) = find_variable_block(child, name, rip) {
+                block = Some(r);
+            }
+        }
+    }
+
+    block
+}
+
+
+// this is the exception callback -- it gets called every time the target process is
+// suspended and starts the main debugger loop
+// the return value tells thorin.c what to do with the target process once we're done
+unsafe extern "C" fn exc_callback(
+    session_p: *mut Session,
+    mut rbp: libc::uintptr_t,
+    mut rip: libc::uintptr_t,
+    mut reason: libc::c_int,
+    info: *const StopInfo
+) -> libc::c_int {
+    let session = &mut (*session_p);
+
+    // a new program means new debugging information, and nothing that was
+    // going on in the old one matters anymore
+    if reason == STOP_EXEC {
+        let pid = get_process_id();
+        let exec_path = match std::fs::read_link(format!("/proc/{}/exe", pid)) {
+            Ok(r) => String::from(r.to_string_lossy()),
+            Err(_) => String::from("??")
+        };
+        println!("Process {} is executing new program: {}", pid, exec_path);
+        switch_image(session, &exec_path);
+        session.breakpoints.clear();
+        session.watchpoints.clear();
+        set_watch_stepping(0);
+        session.step = None;
+        session.line_step = None;
+        session.finished = None;
+    }
+    // system calls only stop the process if a catchpoint caught them
+    if reason == STOP_SYSCALL_ENTRY || reason == STOP_SYSCALL_EXIT {
+        return if catch_syscall(session, reason, (*info).syscall) { ACTION_STOP } else { ACTION_CONTINUE };
+    }
+    if reason == STOP_CATCHPOINT {
+        session.step = None;
+        session.line_step = None;
+        clear_temporary_breakpoints();
+    } else if session.syscall_line.take().is_some() {
+        println!(" <unfinished ...>");
+    }
+    // a restarted process runs the original program again
+    if reason == STOP_ENTRY && session.image != session.target {
+        let target = session.target.clone();
+        switch_image(session, &target);
+    }
+    let load_bias = get_load_bias() as u64;
+    if load_bias != session.load_bias {
+        relocate(session, load_bias);
+    }
+    if reason == STOP_LIBRARIES || reason == STOP_ATTACH {
+        update_libraries(session);
+    }
+
+    // a breakpoint that shouldn't stop the process doesn't get in the way of
+    // whatever the process was doing: a step carries on, and so does the process
+    let mut temporary = false;
+    if reason == STOP_BREAKPOINT || reason == STOP_LIBRARIES {
+        let number = (*info).breakpoint;
+        if reason == STOP_LIBRARIES || !breakpoint_stops(session, number, rip as u64, rbp as i64) {
+            reason = match session.step {
+                Some(Step::Instructions(_)) | Some(Step::IntoCall { .. }) => STOP_STEP,
+                _ if session.watchpoints.iter().any(|w| w.software) => STOP_WATCH_STEP,
+                _ => { return ACTION_CONTINUE; }
+            };
+        } else if session.breakpoints.iter().any(|b| b.number == number && b.temporary) {
+            delete_numbered(&mut session.breakpoints, &mut session.watchpoints, &mut session.catchpoints, number);
+            temporary = true;
+        }
+    }
+
+    // a function with hardware watchpoints on its locals has returned. if it
+    // was a deeper recursive call of it, the scope breakpoint is just in the way
+    // like a breakpoint that shouldn't stop the process
+    if reason == STOP_SCOPE && !check_watchpoint_scopes(session, rip as u64) {
+        reason = match session.step {
+            Some(Step::Instructions(_)) | Some(Step::IntoCall { .. }) => STOP_STEP,
+            Some(Step::OverCall { ret, .. }) | Some(Step::Finish { ret, .. }) if ret == rip as u64 => STOP_STEP,
+            _ if session.watchpoints.iter().any(|w| w.software) => STOP_WATCH_STEP,
+            _ => { return ACTION_CONTINUE; }
+        };
+    }
+    if reason == STOP_WATCHPOINT && check_watchpoint_scopes(session, rip as u64)
+        && !session.watchpoints.iter().any(|w| w.number == (*info).watchpoint) {
+        reason = STOP_SCOPE;
+    }
+
+    // software watchpoints are checked after every instruction. if one of them
+    // changed, whatever step was going on is over
+    let watching = reason == STOP_WATCH_STEP || reason == STOP_STEP || reason == STOP_BREAKPOINT;
+    if watching && session.watchpoints.iter().any(|w| w.software) {
+        if check_software_watchpoints(session, rip as u64) {
+            session.step = None;
+            session.line_step = None;
+            clear_temporary_breakpoints();
+        } else if reason == STOP_WATCH_STEP {
+            return ACTION_CONTINUE;
+        }
+    }
+
+    if let Some(action) = continue_step(session, rip as u64, reason) {
+        return action;
+    }
+    if let Some(action) = continue_line_step(session, rip as u64, reason) {
+        return action;
+    }
+
+    // the process was restarted by 'run', so it shouldn't wait for another one
+    if reason == STOP_ENTRY && session.run_on_entry {
+        session.run_on_entry = false;
+        return ACTION_CONTINUE;
+    }
+    if reason == STOP_EXIT && !session.stop_on_exit {
+        return ACTION_CONTINUE;
+    }
+
+    let scope = &session.scope;
+    let types = &session.types;
+    let lines = &session.lines;
+
+    let mut variables: HashMap<String, Variable> = HashMap::new();
+    let mut scopes: Vec<String> = Vec::new();
+    construct_context(scope, &mut variables, &mut scopes, rip as u64);
+
+    match reason {
+        STOP_ENTRY => {
+            if session.killed {
+                session.killed = false;
+                println!("Process killed. Start it again with 'run'.\n");
+            } else {
+                println!("Process started. Set breakpoints with 'break' and start it with 'run'.\n");
+            }
+        },
+        _ => {
+            if reason == STOP_BREAKPOINT {
+                let kind = if temporary { "Temporary breakpoint" } else { "Breakpoint" };
+                println!("{} {} hit at {:#x}.\n", kind, (*info).breakpoint, rip);
+            } else if reason == STOP_STEP {
+                println!("Stopped at {:#x}.\n", rip);
+            } else if reason == STOP_INTERRUPT {
+                println!("Program interrupted at {:#x}.\n", rip);
+            } else if reason == STOP_ATTACH {
+                println!("Attached to process, stopped at {:#x}.\n", rip);
+            } else if reason == STOP_EXEC {
+                println!("Breakpoints and watchpoints were deleted. Set new ones with 'break' and resume the program with 'continue'.\n");
+            } else if reason == STOP_SWITCH {
+                println!("Switched to process {}, stopped at {:#x}.\n", get_process_id(), rip);
+            } else if reason == STOP_CATCHPOINT {
+                let number = (*info).syscall;
+                let catchpoint = find_catchpoint(&session.catchpoints, number).unwrap_or(0);
+                if (*info).status == 0 {
+                    println!("Catchpoint {} (call to syscall {}).\n", catchpoint, format_syscall(number));
+                } else {
+                    println!("Catchpoint {} (returned from syscall {} = {}).\n", catchpoint, syscall_name(number), format_syscall_return(number));
+                }
+            } else if reason == STOP_EXIT {
+                let status = (*info).status;
+                if libc::WIFSIGNALED(status) {
+                    let signo = libc::WTERMSIG(status);
+                    let description = std::ffi::CStr::from_ptr(strsignal(signo)).to_string_lossy();
+                    println!("Process is about to be terminated by signal {} ({}).\n", signo, description);
+                } else {
+                    println!("Process is about to exit with code {}.\n", libc::WEXITSTATUS(status));
+                }
+            } else if reason == STOP_WATCH_STEP || reason == STOP_SCOPE {
+                // the watchpoint has already been reported
+            } else if reason == STOP_WATCHPOINT {
+                if !report_watchpoint(&mut session.watchpoints, (*info).watchpoint, types) {
+                    return ACTION_CONTINUE;
+                }
+            } else {
+                print_stop_info(&*info);
+            }
+
+            print_location(lines, rip as u64);
+
+            println!("Scope tree:");
+            let mut scope_print_offset = String::from("");
+            for scope_name in scopes {
+                println!("{}-> {}", scope_print_offset, scope_name);
+                scope_print_offset.push_str("  ");
+            }
+
+            println!("\nVariables defined in this scope:");
+            for (key, value) in &variables {
+                println!("  {}: {}", key, value.type_name);
+            }
+
+            println!();
+
+            if let Some((function, type_name)) = session.finished.take() {
+                print_return_value(&function, &type_name, types);
+            }
+        }
     }
 
-    println!("\nVariables defined in this scope:");
-    for (key, value) in &variables {
-        println!("  {}: {}", key, value.type_name);
-    }
+    loop {
+        print!("thorin> "); std::io::stdout().flush().unwrap();
+        let command_s: String = read!("{}\n");
+        let command: Vec<_> = command_s.split_whitespace().collect();
+        let verb = command[0].to_string();
+
+        match verb.as_ref() {
+            "exit" | "quit" => {
+                if !session.attached { break; }
+
+                // we didn't start the process, so it might not be ours to kill
+                print!("Detach from the process and leave it running, or kill it? (detach/kill) ");
+                std::io::stdout().flush().unwrap();
+                let answer: String = read!("{}\n");
+                match answer.trim() {
+                    "d" | "detach" => { return ACTION_DETACH; },
+                    "k" | "kill" => { break; },
+                    _ => { println!("not quitting."); continue; }
+                }
+            },
+            "help" => {
+                println!("Commands:");
+                println!("  (print|show|get) <variable-name>:  Print the value of a variable.");
+                println!("  read <address> <count> <type>:     Read the value at <address>. <type>");
+                println!("                                     is the type of the value, <count> is the");
+                println!("                                     number of values to read.");
+                println!("  (break|b) <function>:              Set a breakpoint at <function>.");
+                println!("  (break|b) <file>:<line>:           Set a breakpoint at <line> in <file>.");
+                println!("  (break|b) <location> if <cond>:    Set a breakpoint that only stops the");
+                println!("                                     program when <cond> is true, e.g");
+                println!("                                     'x > 3 && p.y == 0'.");
+                println!("  tbreak <location> [if <cond>]:     Set a breakpoint that is deleted the");
+                println!("                                     first time it stops the program.");
+                println!("  dprintf <location>,\"<format>\",<args...>: Print <args> with a printf-style");
+                println!("                                     <format> every time <location> is");
+                println!("                                     reached, without stopping.");
+                println!("  ignore <number> <count>:           Don't stop at breakpoint <number> the");
+                println!("                                     next <count> times it is hit.");
+                println!("  enable [numbers...]:               Enable breakpoints and catchpoints.");
+                println!("  disable [numbers...]:              Disable breakpoints and catchpoints.");
+                println!("  (delete|d) [numbers...]:           Delete breakpoints, watchpoints and");
+                println!("                                     catchpoints (all of them if no");
+                println!("                                     numbers are given).");
+                println!("  info breakpoints:                  List breakpoints, watchpoints and");
+                println!("                                     catchpoints.");
+                println!("  watch <expression>:                Stop when <expression> changes. It is a");
+                println!("                                     variable, optionally followed by struct");
+                println!("                                     members, e.g 'point.x', or arithmetic");
+                println!("                                     and comparisons of those, e.g");
+                println!("                                     'p.x + i > 3' (watch only).");
+                println!("  rwatch <expression>:               Stop when <expression> is read.");
+                println!("  awatch <expression>:               Stop when <expression> is read or");
+                println!("                                     written.");
+                println!("  run [args] [< in] [> out] [2> err]: Start the program, optionally with new");
+                println!("                                     arguments and redirections.");
+                println!("  restart:                           Start the program again from the");
+                println!("                                     beginning, keeping breakpoints.");
+                println!("  kill:                              Kill the program without quitting.");
+                println!("                                     If thorin attached to it, this quits.");
+                println!("  checkpoint:                        Save a copy of the program as it is now.");
+                println!("  restart <checkpoint>:              Go back to where <checkpoint> was made.");
+                println!("  info checkpoints:                  List the checkpoints.");
+                println!("  delete checkpoint <number>:        Delete a checkpoint.");
+                println!("  set env <name>=<value>:            Set an environment variable for the");
+                println!("                                     program.");
+                println!("  unset env <name>:                  Remove an environment variable.");
+                println!("  set cwd <directory>:               Set the directory the program runs in.");
+                println!("  set pty (on|off):                  Run the program on its own terminal.");
+                println!("  set disable-randomization (on|off): Load the program at the same addresses");
+                println!("                                     every time it is started.");
+                println!("  set stop-on-exit (on|off):         Suspend the program right before it");
+                println!("                                     exits.");
+                println!("  set hw-watchpoints (on|off):       Use the CPU's debug registers for");
+                println!("                                     watchpoints when possible.");
+                println!("  set follow-fork-mode (parent|child): Keep debugging the parent or switch");
+                println!("                                     to the new process when the program");
+                println!("                                     forks.");
+                println!("  set detach-on-fork (on|off):       Detach from the process that isn't");
+                println!("                                     followed after a fork, or keep it");
+                println!("                                     stopped until the followed one exits.");
+                println!("  output:                            Show what the program has written to");
+                println!("                                     its terminal.");
+                println!("  input <text>:                      Send a line of input to the program's");
+                println!("                                     terminal.");
+                println!("  (continue|c):                      Resume the program until the next");
+                println!("                                     breakpoint or exception.");
+                println!("  (stepi|si) [count]:                Execute one (or <count>) instructions.");
+                println!("  (nexti|ni):                        Execute one instruction, stepping over");
+                println!("                                     function calls.");
+                println!("  (step|s):                          Execute until the next source line,");
+                println!("                                     stepping into function calls.");
+                println!("  (next|n):                          Execute until the next source line,");
+                println!("                                     stepping over function calls.");
+                println!("  finish:                            Execute until the current function");
+                println!("                                     returns and print its return value.");
+                println!("  (reverse-step|rs):                 Go back to the previous source line,");
+                println!("                                     stepping into function calls.");
+                println!("  (reverse-next|rn):                 Go back to the previous source line,");
+                println!("                                     stepping over function calls.");
+                println!("  catch syscall [names...]:          Stop when the program calls or returns");
+                println!("                                     from one of the system calls <names>,");
+                println!("                                     or from any system call.");
+                println!("  handle <signal> <keywords...>:     Change what happens when the program");
+                println!("                                     receives <signal>. Keywords are");
+                println!("                                     (no)stop, (no)pass and (no)print.");
+                println!("  info signals:                      Show what happens for each signal.");
+                println!("  info threads:                      List the program's threads and where");
+                println!("                                     they are stopped.");
+                println!("  info sharedlibrary:                List the shared libraries the program");
+                println!("                                     has loaded.");
+                println!("  thread <number>:                   Switch to thread <number>.");
+                println!("  help:                              Print this help message.");
+                println!("  (exit|quit):                       Quit thorin.");
+
+                continue;
+            },
+            "print" | "show" | "get" => {
+                if command.len() < 2 {
+                    println!("command '{}' expects at least one argument", verb);
+                    println!("Usage: {} <variable-name>", verb);
+                    continue;
+                }
+            },
+            "read" => {
+                if command.len() < 4 {
+                    println!("command '{}' expects at least three arguments", verb);
+                    println!("Usage: {} <address> <count> <type>", verb);
+                    continue;
+                }
+
+                let address_str = command[1].trim_start_matches("0x");
+                let address = match u64::from_str_radix(&address_str, 16) {
+                    Ok(r) => r,
+                    Err(err) => {
+                        println!("error parsing address: {}", err);
+                        continue;
+                    }
+                };
+                let count_str = command[2];
+                let count = match usize::from_str_radix(&count_str, 10) {
+                    Ok(r) => r,
+                    Err(err) => {
+                        println!("error parsing count: {}", err);
+                        continue;
+                    }
+                };
+                let type_name = command[3].to_string();
+
+                read_ptr(address, count, &type_name, &types);
+
+                continue;
+            },
+            "break" | "b" | "tbreak" => {
+                if command.len() < 2 {
+                    println!("command '{}' expects at least one argument", verb);
+                    println!("Usage: {} <function> [if <condition>]", verb);
+                    println!("       {} <file>:<line> [if <condition>]", verb);
+                    continue;
+                }
+
+                // everything after 'if' is the condition
+                let condition = match command.get(2) {
+                    Some(&"if") if command.len() > 3 => Some(command[3..].join(" ")),
+                    None => None,
+                    _ => {
+                        println!("Usage: {} <location> [if <condition>]", verb);
+                        continue;
+                    }
+                };
+                if let Some(ref condition) = condition {
+                    if let Err(err) = check_condition(condition) {
+                        println!("{}", err);
+                        continue;
+                    }
+                }
+
+                let (address, location) = match resolve_location(scope, lines, command[1]) {
+                    Ok(r) => r,
+                    Err(err) => {
+                        println!("{}", err);
+                        continue;
+                    }
+                };
+
+                let number = set_breakpoint(address as libc::uintptr_t);
+                if number == -1 { continue; }
+
+                let temporary = verb == "tbreak";
+                println!("{} {} at {:#x}: {}", if temporary { "Temporary breakpoint" } else { "Breakpoint" }, number, address, location);
+                session.breakpoints.push(Breakpoint {
+                    number,
+                    address,
+                    location,
+                    condition,
+                    ignore: 0,
+                    hits: 0,
+                    temporary,
+                    enabled: true,
+                    dprintf: None,
+                    pending: None
+                });
+
+                continue;
+            },
+            "dprintf" => {
+                let (location, format, args) = match parse_dprintf(command_s.trim()[verb.len()..].trim()) {
+                    Ok(r) => r,
+                    Err(err) => {
+                        println!("{}", err);
+                        println!("Usage: {} <location>,\"<format>\"[,<expression>...]", verb);
+                        continue;
+                    }
+                };
+
+                let (address, location) = match resolve_location(scope, lines, &location) {
+                    Ok(r) => r,
+                    Err(err) => {
+                        println!("{}", err);
+                        continue;
+                    }
+                };
+
+                let number = set_breakpoint(address as libc::uintptr_t);
+                if number == -1 { continue; }
+
+                println!("Dprintf {} at {:#x}: {}", number, address, location);
+                session.breakpoints.push(Breakpoint {
+                    number,
+                    address,
+                    location,
+                    condition: None,
+                    ignore: 0,
+                    hits: 0,
+                    temporary: false,
+                    enabled: true,
+                    dprintf: Some(Dprintf { format, args }),
+                    pending: None
+                });
 
-    println!("");
-    loop {
-        print!("thorin> "); std::io::stdout().flush().unwrap();
-        let command_s: String = read!("{}\n");
-        let command: Vec<_> = command_s.split_whitespace().collect();
-        let verb = command[0].to_string();
+                continue;
+            },
+            "ignore" => {
+                let (number, count) = match (command.get(1).map(|n| n.parse::<libc::c_int>()), command.get(2).map(|c| c.parse::<u64>())) {
+                    (Some(Ok(n)), Some(Ok(c))) => (n, c),
+                    _ => {
+                        println!("Usage: {} <breakpoint> <count>", verb);
+                        continue;
+                    }
+                };
 
-        match verb.as_ref() {
-            "exit" | "quit" => { break; },
-            "help" => {
-                println!("Commands:");
-                println!("  (print|show|get) <variable-name>:  Print the value of a variable.");
-                println!("  read <address> <count> <type>:     Read the value at <address>. <type>");
-                println!("                                     is the type of the value, <count> is the");
-                println!("                                     number of values to read.");
-                println!("  help:                              Print this help message.");
-                println!("  (exit|quit):                       Quit thorin.");
+                match session.breakpoints.iter_mut().find(|b| b.number == number) {
+                    Some(breakpoint) => {
+                        breakpoint.ignore = count;
+                        match count {
+                            0 => { println!("Will stop next time breakpoint {} is reached.", number); },
+                            1 => { println!("Will ignore next crossing of breakpoint {}.", number); },
+                            _ => { println!("Will ignore next {} crossings of breakpoint {}.", count, number); }
+                        }
+                    },
+                    None => { println!("no breakpoint number {}.", number); }
+                }
 
                 continue;
             },
-            "print" | "show" | "get" => {
+            "delete" | "d" if command.get(1) == Some(&"checkpoint") => {
+                let number = match command.get(2).map(|s| s.parse::<libc::c_int>()) {
+                    Some(Ok(n)) => n,
+                    _ => {
+                        println!("Usage: {} checkpoint <number>", verb);
+                        continue;
+                    }
+                };
+                if delete_checkpoint(number) == -1 { continue; }
+
+                session.checkpoints.retain(|c| c.number != number);
+                if session.origin == Some(number) {
+                    session.origin = None;
+                }
+                continue;
+            },
+            "enable" | "disable" | "delete" | "d" => {
+                let mut numbers: Vec<libc::c_int> = Vec::new();
+                let mut valid = true;
+                for word in &command[1..] {
+                    match word.parse::<libc::c_int>() {
+                        Ok(n) => { numbers.push(n); },
+                        Err(_) => {
+                            println!("invalid breakpoint number '{}'", word);
+                            valid = false;
+                        }
+                    }
+                }
+                if !valid { continue; }
+
+                // no numbers means all of them
+                if numbers.is_empty() {
+                    if verb == "delete" || verb == "d" {
+                        print!("Delete all breakpoints, watchpoints and catchpoints? (y/n) ");
+                        std::io::stdout().flush().unwrap();
+                        let answer: String = read!("{}\n");
+                        match answer.trim() {
+                            "y" | "yes" => {},
+                            _ => { continue; }
+                        }
+                    }
+                    numbers.extend(session.breakpoints.iter().map(|b| b.number));
+                    numbers.extend(session.watchpoints.iter().map(|w| w.number));
+                    numbers.extend(session.catchpoints.iter().map(|c| c.number));
+                }
+
+                for number in numbers {
+                    let found = match verb.as_ref() {
+                        "enable" | "disable" => enable_numbered(&mut session.breakpoints, &session.watchpoints,
+                                                                &mut session.catchpoints, number, verb == "enable"),
+                        _ => delete_numbered(&mut session.breakpoints, &mut session.watchpoints,
+                                             &mut session.catchpoints, number)
+                    };
+                    if !found {
+                        println!("no breakpoint number {}.", number);
+                    }
+                }
+                update_syscall_tracing(session);
+
+                continue;
+            },
+            "watch" | "rwatch" | "awatch" => {
                 if command.len() < 2 {
                     println!("command '{}' expects at least one argument", verb);
-                    println!("Usage: {} <variable-name>", verb);
+                    println!("Usage: {} <expression>", verb);
                     continue;
                 }
-            },
-            "read" => {
-                if command.len() < 4 {
-                    println!("command '{}' expects at least three arguments", verb);
-                    println!("Usage: {} <address> <count> <type>", verb);
+
+                let kind = match verb.as_ref() {
+                    "rwatch" => WATCH_READ,
+                    "awatch" => WATCH_ACCESS,
+                    _ => WATCH_WRITE
+                };
+
+                // anything other than a variable or one of its members is
+                // computed from other values, and watched by evaluating it
+                let expression = command_s.trim()[verb.len()..].trim().to_string();
+                let is_path = expression.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+                if !is_path {
+                    if kind != WATCH_WRITE {
+                        println!("only 'watch' can watch a computed expression");
+                        continue;
+                    }
+
+                    let computed = Computed { variables: variables.clone(), base: rbp as i64, value: Value::Int(0) };
+                    let value = match evaluate_computed(&expression, &computed, types) {
+                        Ok(r) => r,
+                        Err(err) => {
+                            println!("{}", err);
+                            continue;
+                        }
+                    };
+
+                    // the expression is only valid as long as all of its
+                    // variables are, i.e in the innermost of their blocks
+                    let block = tokenize_condition(&expression).unwrap_or_default().iter()
+                        .filter(|t| t.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+                        .filter_map(|t| find_variable_block(scope, t.split('.').next().unwrap(), rip as u64))
+                        .min_by_key(|b| b.1);
+
+                    let mut regs = Registers::default();
+                    if get_registers(&mut regs) == -1 { continue; }
+                    let frame = find_function_at(scope, rip as u64)
+                        .map_or(regs.rbp, |f| frame_address(lines, f, rip as u64, &regs));
+                    let number = next_breakpoint_number();
+                    set_watch_stepping(1);
+
+                    let watchpoint = Watchpoint {
+                        number,
+                        kind,
+                        name: expression.clone(),
+                        type_name: String::new(),
+                        address: 0,
+                        old: Vec::new(),
+                        software: true,
+                        block,
+                        frame,
+                        sp: regs.rsp,
+                        ret: 0,
+                        computed: Some(Computed { value, ..computed })
+                    };
+                    println!("{} {}: {}", watchpoint_label(&watchpoint), number, expression);
+                    session.watchpoints.push(watchpoint);
                     continue;
                 }
 
-                let address_str = command[1].trim_start_matches("0x");
-                let address = match u64::from_str_radix(&address_str, 16) {
+                // the variable's address is found the same way as when printing it
+                let (address, type_name) = match resolve_expression(&expression, &variables, types, rbp as i64) {
                     Ok(r) => r,
                     Err(err) => {
-                        println!("error parsing address: {}", err);
+                        println!("{}", err);
                         continue;
                     }
                 };
-                let count_str = command[2];
-                let count = match usize::from_str_radix(&count_str, 10) {
+                let size = type_size(&type_name, types) as usize;
+
+                // the debug registers can only watch a few small, aligned values
+                let hardware_count = session.watchpoints.iter().filter(|w| !w.software).count();
+                let fits = (size == 1 || size == 2 || size == 4 || size == 8) && address % size as u64 == 0;
+                let software = !session.hw_watchpoints || !fits || hardware_count >= 4;
+                if software && kind != WATCH_WRITE {
+                    println!("'{}' can't be watched with a hardware watchpoint, and only 'watch' can", expression);
+                    println!("use a software one");
+                    continue;
+                }
+
+                let number = if software {
+                    next_breakpoint_number()
+                } else {
+                    set_watchpoint(address as libc::uintptr_t, size as libc::c_int, kind)
+                };
+                if number == -1 { continue; }
+
+                let mut regs = Registers::default();
+                if get_registers(&mut regs) == -1 { continue; }
+                if software { set_watch_stepping(1); }
+                let frame = find_function_at(scope, rip as u64)
+                    .map_or(regs.rbp, |f| frame_address(lines, f, rip as u64, &regs));
+
+                // a hardware watchpoint on a local variable would go on firing
+                // for whatever reuses its stack slot once the function returns
+                let varname = expression.split('.').next().unwrap();
+                let block = find_variable_block(scope, varname, rip as u64);
+                let mut ret: u64 = 0;
+                if !software && block.is_some() {
+                    read_addr(&mut ret as *mut u64 as *mut libc::c_void, (frame + 8) as libc::uintptr_t, 8);
+                    if set_scope_breakpoint(ret as libc::uintptr_t) == -1 { ret = 0; }
+                }
+
+                let mut old = vec![0u8; size];
+                read_addr(old.as_mut_ptr() as *mut libc::c_void, address as libc::uintptr_t, size);
+                let watchpoint = Watchpoint {
+                    number,
+                    kind,
+                    name: expression.clone(),
+                    type_name,
+                    address,
+                    old,
+                    software,
+                    block,
+                    frame,
+                    sp: regs.rsp,
+                    ret,
+                    computed: None
+                };
+                println!("{} {}: {}", watchpoint_label(&watchpoint), number, expression);
+                session.watchpoints.push(watchpoint);
+
+                continue;
+            },
+            "run" => {
+                if reason != STOP_ENTRY {
+                    if session.attached {
+                        println!("thorin attached to this process, so it can't start it again");
+                        continue;
+                    }
+
+                    print!("The program is already running. Start it from the beginning? (y/n) ");
+                    std::io::stdout().flush().unwrap();
+                    let answer: String = read!("{}\n");
+                    match answer.trim() {
+                        "y" | "yes" => { session.restart = true; },
+                        _ => { println!("not restarting."); continue; }
+                    }
+                }
+
+                if command.len() > 1 {
+                    let (args, io) = match parse_redirections(&command[1..]) {
+                        Ok(r) => r,
+                        Err(err) => {
+                            println!("{}", err);
+                            continue;
+                        }
+                    };
+                    session.args = args;
+                    session.io = io;
+                    session.restart = true;
+                }
+
+                // the process was started with the old settings, so start it again
+                if session.restart {
+                    return respawn(session, true);
+                }
+
+                return ACTION_CONTINUE;
+            },
+            "checkpoint" => {
+                if reason == STOP_ENTRY {
+                    println!("the program has not been started yet, use 'run'");
+                    continue;
+                }
+
+                let number = make_checkpoint();
+                if number == -1 { continue; }
+
+                session.checkpoints.push(Checkpoint { number, address: rip as u64 });
+                session.origin = Some(number);
+                println!("Checkpoint {} at {:#x}.", number, rip);
+                continue;
+            },
+            "restart" | "reverse-step" | "rs" | "reverse-next" | "rn" if verb != "restart" || command.len() > 1 => {
+                if reason == STOP_ENTRY {
+                    println!("the program has not been started yet, use 'run'");
+                    continue;
+                }
+
+                // either way, the target process is replaced by a copy of a
+                // checkpoint
+                let result = if verb == "restart" {
+                    match command[1].parse::<libc::c_int>() {
+                        Ok(number) => {
+                            let old = start_checkpoint(number);
+                            if old == -1 { continue; }
+                            kill_process(old);
+                            insert_breakpoints();
+                            session.origin = Some(number);
+                            Ok(format!("Switched to checkpoint {}", number))
+                        },
+                        Err(_) => Err(format!("invalid checkpoint number '{}'", command[1]))
+                    }
+                } else {
+                    let over_calls = verb == "reverse-next" || verb == "rn";
+                    reverse_step(session, over_calls).map(|_| String::from("Stepped back"))
+                };
+                let message = match result {
                     Ok(r) => r,
                     Err(err) => {
-                        println!("error parsing count: {}", err);
+                        println!("{}", err);
                         continue;
                     }
                 };
-                let type_name = command[3].to_string();
 
-                read_ptr(address, count, &type_name, &types);
+                // the watchpoints were in the old process's memory
+                session.watchpoints.clear();
+                set_watch_stepping(0);
+                session.step = None;
+                session.line_step = None;
+                session.finished = None;
+                session.syscall_pending.clear();
+                session.syscall_line = None;
+
+                let (new_rbp, new_rip) = match current_frame() {
+                    Some(r) => r,
+                    None => { continue; }
+                };
+                rbp = new_rbp;
+                rip = new_rip;
+                reason = STOP_STEP;
+
+                variables = HashMap::new();
+                let mut scopes: Vec<String> = Vec::new();
+                construct_context(scope, &mut variables, &mut scopes, rip as u64);
+
+                let function = find_function_at(scope, rip as u64)
+                    .and_then(|f| f.name.clone())
+                    .unwrap_or(String::from("??"));
+                println!("{}, stopped at {:#x} in {}.\n", message, rip, function);
+                print_location(lines, rip as u64);
+                continue;
+            },
+            "restart" => {
+                if session.attached {
+                    println!("thorin attached to this process, so it can't start it again");
+                    continue;
+                }
+                if reason == STOP_ENTRY && !session.restart {
+                    return ACTION_CONTINUE;
+                }
+
+                return respawn(session, true);
+            },
+            "kill" => {
+                if reason == STOP_ENTRY {
+                    println!("the program is not running");
+                    continue;
+                }
+
+                // there is no way to start a process that we attached to again
+                if session.attached {
+                    println!("thorin attached to this process, so it can't start it again. quitting.");
+                    break;
+                }
+
+                session.killed = true;
+                return respawn(session, false);
+            },
+            "set" | "unset" => {
+                let setting = command.get(1).map(|s| s.to_string()).unwrap_or(String::new());
+                match (verb.as_ref(), setting.as_ref()) {
+                    ("set", "env") if command.len() > 2 => {
+                        let (name, value) = match command[2].find('=') {
+                            Some(eq) => (command[2][..eq].to_string(), command[2][eq + 1..].to_string()),
+                            None => (command[2].to_string(), command[3..].join(" "))
+                        };
+                        session.env.retain(|(n, _)| *n != *name);
+                        session.env.push((OsString::from(name), OsString::from(value)));
+                    },
+                    ("unset", "env") if command.len() > 2 => {
+                        let name = command[2];
+                        if !session.env.iter().any(|(n, _)| n == name) {
+                            println!("environment variable '{}' is not set", name);
+                            continue;
+                        }
+                        session.env.retain(|(n, _)| n != name);
+                    },
+                    ("set", "cwd") if command.len() > 2 => {
+                        let directory = command[2..].join(" ");
+                        if !Path::new(&directory).is_dir() {
+                            println!("'{}' is not a directory", directory);
+                            continue;
+                        }
+                        session.cwd = Some(directory);
+                    },
+                    ("set", "disable-randomization") if command.len() > 2 => {
+                        session.disable_randomization = match command[2] {
+                            "on" => true,
+                            "off" => false,
+                            other => {
+                                println!("expected 'on' or 'off', got '{}'", other);
+                                continue;
+                            }
+                        };
+                        set_disable_randomization(session.disable_randomization as libc::c_int);
+                    },
+                    ("set", "stop-on-exit") if command.len() > 2 => {
+                        session.stop_on_exit = match command[2] {
+                            "on" => true,
+                            "off" => false,
+                            other => {
+                                println!("expected 'on' or 'off', got '{}'", other);
+                                continue;
+                            }
+                        };
+
+                        // this doesn't change how the process is started
+                        continue;
+                    },
+                    ("set", "hw-watchpoints") if command.len() > 2 => {
+                        session.hw_watchpoints = match command[2] {
+                            "on" => true,
+                            "off" => false,
+                            other => {
+                                println!("expected 'on' or 'off', got '{}'", other);
+                                continue;
+                            }
+                        };
+
+                        // this doesn't change how the process is started
+                        continue;
+                    },
+                    ("set", "follow-fork-mode") if command.len() > 2 => {
+                        session.follow_fork_child = match command[2] {
+                            "parent" => false,
+                            "child" => true,
+                            other => {
+                                println!("expected 'parent' or 'child', got '{}'", other);
+                                continue;
+                            }
+                        };
+                        set_fork_mode(session.follow_fork_child as libc::c_int, session.detach_on_fork as libc::c_int);
+                        continue;
+                    },
+                    ("set", "detach-on-fork") if command.len() > 2 => {
+                        session.detach_on_fork = match command[2] {
+                            "on" => true,
+                            "off" => false,
+                            other => {
+                                println!("expected 'on' or 'off', got '{}'", other);
+                                continue;
+                            }
+                        };
+                        set_fork_mode(session.follow_fork_child as libc::c_int, session.detach_on_fork as libc::c_int);
+                        continue;
+                    },
+                    ("set", "pty") if command.len() > 2 => {
+                        session.pty = match command[2] {
+                            "on" => true,
+                            "off" => false,
+                            other => {
+                                println!("expected 'on' or 'off', got '{}'", other);
+                                continue;
+                            }
+                        };
+                    },
+                    _ => {
+                        println!("Usage: set env <name>=<value>");
+                        println!("       unset env <name>");
+                        println!("       set cwd <directory>");
+                        println!("       set pty (on|off)");
+                        println!("       set disable-randomization (on|off)");
+                        println!("       set stop-on-exit (on|off)");
+                        println!("       set hw-watchpoints (on|off)");
+                        println!("       set follow-fork-mode (parent|child)");
+                        println!("       set detach-on-fork (on|off)");
+                        continue;
+                    }
+                }
+
+                session.restart = true;
+                if reason != STOP_ENTRY {
+                    println!("this takes effect the next time the program is started");
+                }
+                continue;
+            },
+            "output" => {
+                let mut buffer = [0u8; 4096];
+                let mut shown = false;
+                loop {
+                    let count = read_inferior_output(buffer.as_mut_ptr() as *mut libc::c_void, buffer.len());
+                    if count == -1 {
+                        println!("the program is not running on its own terminal, use 'set pty on'");
+                        break;
+                    }
+                    if count == 0 { break; }
+
+                    std::io::stdout().write_all(&buffer[..count as usize]).unwrap();
+                    shown = true;
+                }
+                if shown { println!(); }
+
+                continue;
+            },
+            "input" => {
+                // everything after the command is sent, followed by a newline
+                let mut text = command_s.trim_start()[verb.len()..].trim_start().to_string();
+                text.push('\n');
+                if write_inferior_input(text.as_ptr() as *const libc::c_void, text.len()) == -1 {
+                    println!("the program is not running on its own terminal, use 'set pty on'");
+                }
+
+                continue;
+            },
+            "catch" => {
+                if command.get(1) != Some(&"syscall") {
+                    println!("Usage: {} syscall [name|number...]", verb);
+                    continue;
+                }
+
+                let mut syscalls = Vec::new();
+                let mut valid = true;
+                for name in &command[2..] {
+                    match syscall_number(name) {
+                        Some(number) => { syscalls.push(number); },
+                        None => {
+                            println!("unknown system call '{}'", name);
+                            valid = false;
+                        }
+                    }
+                }
+                if !valid { continue; }
+
+                let number = next_breakpoint_number();
+                if syscalls.is_empty() {
+                    println!("Catchpoint {} (any syscall)", number);
+                } else {
+                    let names: Vec<String> = syscalls.iter().map(|n| syscall_name(*n as libc::c_int)).collect();
+                    println!("Catchpoint {} (syscall {})", number, names.join(" "));
+                }
+                session.catchpoints.push(Catchpoint { number, syscalls, enabled: true });
+                set_syscall_tracing(1);
+
+                continue;
+            },
+            "handle" => {
+                if command.len() < 2 {
+                    println!("command '{}' expects at least one argument", verb);
+                    println!("Usage: {} <signal> [stop|nostop] [pass|nopass] [print|noprint]", verb);
+                    continue;
+                }
+
+                // -1 leaves a setting as it is
+                let (mut stop, mut pass, mut print) = (-1, -1, -1);
+                let mut valid = true;
+                for keyword in &command[2..] {
+                    match *keyword {
+                        "stop" => { stop = 1; },
+                        "nostop" => { stop = 0; },
+                        "pass" | "noignore" => { pass = 1; },
+                        "nopass" | "ignore" => { pass = 0; },
+                        "print" => { print = 1; },
+                        "noprint" => { print = 0; },
+                        other => {
+                            println!("unrecognized keyword '{}'", other);
+                            valid = false;
+                        }
+                    }
+                }
+                if !valid { continue; }
+
+                let name = std::ffi::CString::new(command[1]).unwrap();
+                handle_signal(name.as_ptr(), stop, pass, print);
+
+                continue;
+            },
+            "info" => {
+                match command.get(1).copied() {
+                    Some("signals") => { print_signals(); },
+                    Some("threads") => { print_threads(scope, lines); },
+                    Some("breakpoints") | Some("break") | Some("b") => { print_breakpoints(session); },
+                    Some("checkpoints") => { print_checkpoints(session); },
+                    Some("sharedlibrary") => { print_libraries(session); },
+                    _ => {
+                        println!("Usage: {} (signals|threads|breakpoints|checkpoints|sharedlibrary)", verb);
+                    }
+                }
+
+                continue;
+            },
+            "thread" => {
+                let number = match command.get(1).map(|s| s.parse::<libc::c_int>()) {
+                    Some(Ok(n)) => n,
+                    _ => {
+                        println!("Usage: {} <number>", verb);
+                        continue;
+                    }
+                };
+                if select_thread(number) == -1 { continue; }
+
+                // everything from here on is about the new thread
+                let (new_rbp, new_rip) = match current_frame() {
+                    Some(r) => r,
+                    None => { continue; }
+                };
+                rbp = new_rbp;
+                rip = new_rip;
 
+                variables = HashMap::new();
+                let mut scopes: Vec<String> = Vec::new();
+                construct_context(scope, &mut variables, &mut scopes, rip as u64);
+
+                let function = find_function_at(scope, rip as u64)
+                    .and_then(|f| f.name.clone())
+                    .unwrap_or(String::from("??"));
+                println!("Switched to thread {}, stopped at {:#x} in {}.\n", number, rip, function);
+                print_location(lines, rip as u64);
                 continue;
+            },
+            "continue" | "c" => {
+                if reason == STOP_ENTRY {
+                    println!("the program has not been started yet, use 'run'");
+                    continue;
+                }
+
+                return ACTION_CONTINUE;
+            },
+            "stepi" | "si" => {
+                if reason == STOP_ENTRY {
+                    println!("the program has not been started yet, use 'run'");
+                    continue;
+                }
+
+                let count = if command.len() < 2 { 1 } else {
+                    match command[1].parse::<u64>() {
+                        Ok(r) if r > 0 => r,
+                        _ => {
+                            println!("error parsing count '{}'", command[1]);
+                            continue;
+                        }
+                    }
+                };
+
+                session.step = Some(Step::Instructions(count));
+                return ACTION_STEP;
+            },
+            "nexti" | "ni" => {
+                if reason == STOP_ENTRY {
+                    println!("the program has not been started yet, use 'run'");
+                    continue;
+                }
+
+                session.step = Some(if is_call_instruction(rip as u64) {
+                    Step::IntoCall { step_in: false }
+                } else {
+                    Step::Instructions(1)
+                });
+                return ACTION_STEP;
+            },
+            "step" | "s" | "next" | "n" => {
+                if reason == STOP_ENTRY {
+                    println!("the program has not been started yet, use 'run'");
+                    continue;
+                }
+
+                let row = match find_line(lines, rip as u64) {
+                    Some(r) => r,
+                    None => {
+                        println!("no line information for {:#x}, use 'stepi' instead", rip);
+                        continue;
+                    }
+                };
+
+                let over_calls = verb == "next" || verb == "n";
+                session.line_step = Some(LineStep {
+                    file: row.file.clone(),
+                    line: row.line,
+                    over_calls
+                });
+                session.step = Some(if is_call_instruction(rip as u64) {
+                    Step::IntoCall { step_in: !over_calls }
+                } else {
+                    Step::Instructions(1)
+                });
+                return ACTION_STEP;
+            },
+            "finish" => {
+                if reason == STOP_ENTRY {
+                    println!("the program has not been started yet, use 'run'");
+                    continue;
+                }
+
+                let function = match find_function_at(scope, rip as u64) {
+                    Some(r) => r,
+                    None => {
+                        println!("cannot tell which function the program is in");
+                        continue;
+                    }
+                };
+
+                let mut regs = Registers::default();
+                if get_registers(&mut regs) == -1 { continue; }
+
+                let frame = frame_address(lines, function, rip as u64, &regs);
+                let mut ret: u64 = 0;
+                read_addr(&mut ret as *mut u64 as *mut libc::c_void, (frame + 8) as libc::uintptr_t, 8);
+
+                if set_temporary_breakpoint(ret as libc::uintptr_t) == -1 { continue; }
+
+                let name = function.name.clone().unwrap_or(String::from("unnamed function"));
+                println!("Run till exit from {}", name);
+                let mut count: libc::c_int = 0;
+                session.step = Some(Step::Finish {
+                    ret,
+                    sp: frame + 16,
+                    tid: get_thread_id(&mut count),
+                    function: name,
+                    type_name: function.type_name.clone()
+                });
+                return ACTION_CONTINUE;
             }
 
             other => { println!("unknown command '{}'", other); continue; }
@@ -704,19 +4761,154 @@ unsafe extern "C" fn exc_callback(
         let type_name = &variables.get(&varname).unwrap().type_name;
         let addr = (rbp as i64) + offset;
 
-        print_struct("", &varname, &type_name, addr, &types);
+        print_struct("", &varname, type_name, addr, types, &read_process);
     }
 
-    println!("");
-    let mut rng = rand::thread_rng();
-    match rng.gen_range(0, 4) {
-        0 => { println!("\"If more people valued home, above gold, this world would be a merrier place...\""); },
-        1 => { println!("\"We are sons of Durin. And Durin's Folk do not flee from a fight.\""); },
-        2 => { println!("\"Those who have lived through dragon fire should rejoice. They have much to be grateful for.\""); },
-        _ => { println!("\"If this is to end in fire, then we will all burn together.\""); }
+    ACTION_KILL
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Result<Value, String> {
+        match name {
+            "x" => Ok(Value::Int(5)),
+            "y" => Ok(Value::Float(1.5)),
+            "p.x" => Ok(Value::Int(-2)),
+            _ => Err(format!("unrecognized variable '{}'.", name))
+        }
+    }
+
+    #[test]
+    fn tokenize_condition_splits_operators_and_members() {
+        let tokens = tokenize_condition("p.x>=3&&!(y - 1)").unwrap();
+        assert_eq!(tokens, vec!["p.x", ">=", "3", "&&", "!", "(", "y", "-", "1", ")"]);
+        assert!(tokenize_condition("x @ 1").is_err());
+    }
+
+    #[test]
+    fn evaluate_expression_follows_c_precedence() {
+        assert_eq!(evaluate_expression("1 + 2 * 3", &lookup), Ok(Value::Int(7)));
+        assert_eq!(evaluate_expression("(1 + 2) * 3", &lookup), Ok(Value::Int(9)));
+        assert_eq!(evaluate_expression("-x + 7 % 4", &lookup), Ok(Value::Int(-2)));
+        assert_eq!(evaluate_expression("1 < 2 == 1", &lookup), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn evaluate_expression_mixes_integers_and_floats() {
+        assert_eq!(evaluate_expression("7 / 2", &lookup), Ok(Value::Int(3)));
+        assert_eq!(evaluate_expression("x / 2 + y", &lookup), Ok(Value::Float(3.5)));
+        assert_eq!(evaluate_expression("y * 2", &lookup), Ok(Value::Float(3.0)));
+    }
+
+    #[test]
+    fn evaluate_expression_reports_errors() {
+        assert!(evaluate_expression("x / 0", &lookup).is_err());
+        assert!(evaluate_expression("1 2", &lookup).is_err());
+        assert!(evaluate_expression("(1 + 2", &lookup).is_err());
+        assert!(evaluate_expression("z + 1", &lookup).is_err());
+    }
+
+    #[test]
+    fn evaluate_condition_combines_comparisons() {
+        assert_eq!(evaluate_condition("x > 3 && p.x == -2", &lookup), Ok(true));
+        assert_eq!(evaluate_condition("x > 3 && !(y > 1)", &lookup), Ok(false));
+        assert_eq!(evaluate_condition("x < 3 || y >= 1.5", &lookup), Ok(true));
+        assert!(check_condition("x >").is_err());
+        assert!(check_condition("a.b != 0").is_ok());
+    }
+
+    #[test]
+    fn parse_dprintf_splits_location_format_and_arguments() {
+        let (location, format, args) = parse_dprintf("foo.c:42, \"x=%d\\t%s\\n\", x, p.name").unwrap();
+        assert_eq!(location, "foo.c:42");
+        assert_eq!(format, "x=%d\t%s\n");
+        assert_eq!(args, vec!["x", "p.name"]);
+
+        let (location, format, args) = parse_dprintf("main,\"a \\\"quote\\\"\"").unwrap();
+        assert_eq!(location, "main");
+        assert_eq!(format, "a \"quote\"");
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn parse_dprintf_rejects_malformed_arguments() {
+        assert!(parse_dprintf("foo.c:42").is_err());
+        assert!(parse_dprintf("foo.c:42,x=%d,x").is_err());
+        assert!(parse_dprintf("foo.c:42,\"x=%d").is_err());
+        assert!(parse_dprintf("foo.c:42,\"x=%d\" x").is_err());
+        assert!(parse_dprintf("foo.c:42,\"x=%d\",").is_err());
+        assert!(parse_dprintf("foo.c:42,\"x=%d\",x >").is_err());
+    }
+
+    #[test]
+    fn format_printf_formats_integers() {
+        let values = vec![Value::Int(42), Value::Int(-7), Value::Int(255), Value::Int(255)];
+        assert_eq!(format_printf("%d|%5d|%x|%#X", &values), Ok(String::from("42|   -7|ff|0XFF")));
+        assert_eq!(format_printf("%-4d|%04d|%+d|% d", &[Value::Int(3), Value::Int(-3), Value::Int(3), Value::Int(3)]),
+                   Ok(String::from("3   |-003|+3| 3")));
+        assert_eq!(format_printf("%u %o %.3d 100%%", &[Value::Int(-1), Value::Int(8), Value::Int(5)]),
+                   Ok(String::from("4294967295 10 005 100%")));
+        assert_eq!(format_printf("%c%c", &[Value::Int(104), Value::Int(105)]), Ok(String::from("hi")));
+    }
+
+    #[test]
+    fn format_printf_formats_the_most_negative_integers() {
+        assert_eq!(format_printf("%ld", &[Value::Int(i64::MIN)]), Ok(String::from("-9223372036854775808")));
+        assert_eq!(format_printf("%d", &[Value::Int(i32::MIN as i64)]), Ok(String::from("-2147483648")));
+        assert_eq!(format_printf("%lx", &[Value::Int(-1)]), Ok(String::from("ffffffffffffffff")));
+    }
+
+    #[test]
+    fn format_printf_formats_floats() {
+        assert_eq!(format_printf("%f|%.2f|%8.3f", &[Value::Float(1.5), Value::Float(2.0 / 3.0), Value::Int(-2)]),
+                   Ok(String::from("1.500000|0.67|  -2.000")));
+        assert_eq!(format_printf("%e|%.1E", &[Value::Float(1234.5), Value::Float(0.00012)]),
+                   Ok(String::from("1.234500e+03|1.2E-04")));
+        assert_eq!(format_printf("%g|%g|%g|%g", &[Value::Float(0.0001), Value::Float(0.00001), Value::Float(100000.0), Value::Float(1e6)]),
+                   Ok(String::from("0.0001|1e-05|100000|1e+06")));
+        assert_eq!(format_printf("%+.1f", &[Value::Float(0.25)]), Ok(String::from("+0.2")));
+    }
+
+    #[test]
+    fn format_printf_checks_its_arguments() {
+        assert!(format_printf("%d %d", &[Value::Int(1)]).is_err());
+        assert!(format_printf("%d", &[Value::Int(1), Value::Int(2)]).is_err());
+        assert!(format_printf("%", &[Value::Int(1)]).is_err());
+        assert!(format_printf("%n", &[Value::Int(1)]).is_err());
+    }
+
+    #[test]
+    fn format_exponent_matches_c() {
+        assert_eq!(format_exponent(12345.678, 2, false), "1.23e+04");
+        assert_eq!(format_exponent(0.000123, 3, true), "1.230E-04");
+        assert_eq!(format_exponent(0.0, 1, false), "0.0e+00");
+        assert_eq!(format_exponent(-1e100, 0, false), "-1e+100");
+    }
+
+    #[test]
+    fn syscall_number_accepts_names_and_numbers() {
+        assert_eq!(syscall_number("openat"), Some(257));
+        assert_eq!(syscall_number("read"), Some(0));
+        assert_eq!(syscall_number("42"), Some(42));
+        assert_eq!(syscall_number("nosuch"), None);
+    }
+
+    #[test]
+    fn syscall_name_falls_back_to_the_number() {
+        assert_eq!(syscall_name(1), "write");
+        assert_eq!(syscall_name(257), "openat");
+        assert_eq!(syscall_name(9999), "syscall_9999");
     }
-    println!("");
 
-    Box::from_raw(scope_p);
-    Box::from_raw(types_p);
+    #[test]
+    fn format_flags_shows_leftover_bits() {
+        assert_eq!(format_flags(0x3, PROT_FLAGS), "PROT_READ|PROT_WRITE");
+        assert_eq!(format_flags(0x22, MAP_FLAGS), "MAP_PRIVATE|MAP_ANONYMOUS");
+        assert_eq!(format_flags(0x3, MAP_FLAGS), "MAP_SHARED_VALIDATE");
+        assert_eq!(format_flags(0x9, PROT_FLAGS), "PROT_READ|0x8");
+        assert_eq!(format_flags(0, PROT_FLAGS), "0x0");
+    }
 }
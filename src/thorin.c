@@ -34,22 +34,166 @@
 #include <errno.h>
 #include <string.h>
 #include <unistd.h>
+#include <dirent.h>
+#include <sys/syscall.h>
+#include <sys/ioctl.h>
+#include <fcntl.h>
+#include <sys/personality.h>
+#include <stddef.h>
+#include <limits.h>
 
-// we use this struct to read register information of a ptraced
-// process on linux.
-// more generally, it is an IO-vector which is a packaged array+length
-// datatype, used by many IO-related syscalls
-struct iovec {
-  void *iov_base;
-  unsigned int iov_len;
-};
+// we use struct iovec to read register information of a ptraced process
+#include <sys/uio.h>
 
 #endif
 
-typedef void (*exc_callback)(void*, void*, uintptr_t, uintptr_t);
+// reasons for which the child process can be suspended. the rust callback
+// receives one of these so it knows what to tell the user
+#define STOP_ENTRY      0 // the child has just been started and has not run yet
+#define STOP_BREAKPOINT 1 // the child hit a breakpoint
+#define STOP_EXCEPTION  2 // the child raised an exception, e.g a segfault
+#define STOP_STEP       3 // the child finished a step, e.g it executed a single
+                          // instruction or hit a temporary breakpoint
+#define STOP_ATTACH     4 // we have just attached to an already running process
+#define STOP_EXIT       5 // the child is about to exit, but hasn't yet
+#define STOP_WATCHPOINT 6 // the child accessed memory that is being watched
+#define STOP_WATCH_STEP 7 // the child executed an instruction while software
+                          // watchpoints are being checked
+#define STOP_EXEC       8 // the child has just executed a new program
+#define STOP_SWITCH     9 // the process we were following is gone, and we
+                          // switched to one that it forked
+#define STOP_SYSCALL_ENTRY 10 // the child is about to make a system call
+#define STOP_SYSCALL_EXIT  11 // the child has just returned from a system call
+#define STOP_CATCHPOINT    12 // the rust side wants to stop at a system call
+#define STOP_INTERRUPT     13 // the user pressed Ctrl-C
+#define STOP_LIBRARIES     14 // the dynamic linker has loaded or unloaded
+                              // shared libraries
+#define STOP_SCOPE         15 // the child returned to a scope breakpoint
+
+// actions that the rust callback can ask us to perform once it returns
+#define ACTION_KILL     0 // kill the child and end the session
+#define ACTION_CONTINUE 1 // resume the child
+#define ACTION_STEP     2 // execute a single instruction in the child
+#define ACTION_DETACH   3 // stop tracing the child and leave it running
+#define ACTION_RESTART  4 // kill the child and start it again
+#define ACTION_STOP     5 // stop every thread and report the system call
+                          // again as STOP_CATCHPOINT
+
+// the general purpose registers of the child process. the OS gives us these
+// in a different struct on every platform, so we copy them into this one
+struct registers {
+  uint64_t rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp;
+  uint64_t r8, r9, r10, r11, r12, r13, r14, r15;
+  uint64_t rip, rflags;
+};
+
+// why the child was suspended by a signal, as told to us by the OS. the rust
+// callback receives this with every stop, with `signo` set to 0 if the child
+// wasn't stopped by a signal.
+// `address` is the faulting address (for SIGSEGV, SIGBUS, SIGILL and SIGFPE),
+// `sender` is the process that sent the signal (if one did), and `trap` is set
+// if the child executed a trap instruction (ud2 or an int3 that isn't ours).
+// for STOP_EXIT, `status` is the wait status that the child is exiting with
+// and for STOP_BREAKPOINT and STOP_WATCHPOINT, `breakpoint` and `watchpoint`
+// are the numbers of the ones that fired.
+// for STOP_SYSCALL_ENTRY, STOP_SYSCALL_EXIT and STOP_CATCHPOINT, `syscall` is
+// the number of the system call, and for STOP_CATCHPOINT, `status` is set if
+// it is returning
+struct stop_info {
+  int signo;
+  int code;
+  uintptr_t address;
+  int sender;
+  int trap;
+  int status;
+  int breakpoint;
+  int watchpoint;
+  int syscall;
+};
+
+typedef int (*exc_callback)(void*, uintptr_t, uintptr_t, int, const struct stop_info*);
 static exc_callback global_cb; // rust callback
-static void *global_scope;     // the program's scope tree
-static void *global_types;     // type information i.e typedefs/enums/structs
+static void *global_session;   // the debugging session i.e scope tree, types, line table
+
+// how the child process is started: the program, its arguments and environment,
+// and the directory it runs in (NULL to use ours). these are copies that we own,
+// since they can be changed between runs
+static char *global_target;
+static char **global_argv;
+static char **global_envp;
+static char *global_cwd;
+
+// where the standard streams of the child process go: files to redirect
+// stdin/stdout/stderr to (NULL to leave them alone), and whether to give the
+// child its own pseudo-terminal. `global_pty` is our end of that terminal
+static char *global_io[3];
+static int global_use_pty = 0;
+static int global_pty = -1;
+
+// whether the child is started with address space randomization turned off,
+// so that its stack and heap are in the same place every time it runs
+static int global_disable_randomization = 1;
+
+// a software breakpoint is an int3 instruction (0xCC) written over the first
+// byte of an instruction in the child process. we keep the original byte
+// around so that we can put it back when the breakpoint is hit.
+// a breakpoint that has been hit is "lifted" (the original byte is put back)
+// until the child has moved past it.
+// temporary breakpoints are used internally, e.g to step over function calls.
+// they have no number and are removed as soon as they are hit
+struct breakpoint {
+  uintptr_t address;
+  uint8_t original_byte;
+  int number;
+  int active;
+  int inserted;
+};
+
+#define MAX_BREAKPOINTS 64
+#define INT3 0xCC
+
+static struct breakpoint global_breakpoints[MAX_BREAKPOINTS];
+static int global_breakpoint_number = 0; // number of the last breakpoint set by the user
+
+// the dynamic linker keeps a list of the shared libraries that it has loaded
+// in its _r_debug variable, and calls _dl_debug_state every time it has
+// changed the list. a breakpoint there, which the user never sees, tells us
+// when to look at the list again
+#define SHLIB_BREAKPOINT -2
+static uintptr_t global_r_debug = 0;
+
+// a hardware watchpoint on a local variable has to go away when its function
+// returns, so the rust side puts a scope breakpoint on the return address
+#define SCOPE_BREAKPOINT -3
+
+// a hardware watchpoint makes the CPU trap when the child accesses `size`
+// bytes at `address`. x86 has four debug registers (DR0-DR3) to hold their
+// addresses, and DR7 says what kind of access each one is watching for.
+// watchpoints are numbered along with breakpoints
+struct watchpoint {
+  uintptr_t address;
+  int size;
+  int kind;
+  int number;
+  int active;
+};
+
+// kinds of watchpoints
+#define WATCH_WRITE  0 // trap when the child writes the memory
+#define WATCH_READ   1 // trap when the child reads the memory
+#define WATCH_ACCESS 2 // trap when the child reads or writes the memory
+
+#define MAX_WATCHPOINTS 4
+
+static struct watchpoint global_watchpoints[MAX_WATCHPOINTS];
+
+// software watchpoints are checked by the rust side after every instruction,
+// so while there are any, resuming the child means single-stepping it
+static int global_watch_stepping = 0;
+
+// while system calls are being traced, the child stops every time it makes
+// one and every time one returns
+static int global_syscall_tracing = 0;
 
 
 #ifdef __APPLE__
@@ -107,8 +251,9 @@ kern_return_t catch_mach_exception_raise_state_identity (
   )
 {
   x86_thread_state64_t state = *(x86_thread_state64_t *)old_state;
+  struct stop_info info = { 0 };
 
-  global_cb(global_scope, global_types, state.__rbp, state.__rip);
+  global_cb(global_session, state.__rbp, state.__rip, STOP_EXCEPTION, &info);
 
   return KERN_FAILURE;
 }
@@ -116,162 +261,2392 @@ kern_return_t catch_mach_exception_raise_state_identity (
 #elif __linux__
 
 static pid_t global_child = 0; // pid of child process
+static pid_t global_thread = 0; // the thread whose registers the user sees
+
+// the user interrupts the child by pressing Ctrl-C, which sends us a SIGINT.
+// `global_interrupted` is set when that happens, and `global_child_running`
+// while the child is running. if the child is in our process group, it gets
+// the SIGINT from the terminal as well (`global_shares_terminal`)
+static volatile sig_atomic_t global_interrupted = 0;
+static volatile sig_atomic_t global_child_running = 0;
+static int global_shares_terminal = 0;
+
+// how the child process ended, in the form of a shell exit status: its exit
+// code, or 128 + the signal that terminated it
+static int global_exit_status = 0;
+
+// how far the child's executable was loaded from the addresses it was linked
+// at. this is 0 unless it is a position-independent executable
+static uintptr_t global_load_bias = 0;
+
+// when the child forks, we either keep following it or follow the new process
+// instead. the other one is detached from, unless `global_detach_on_fork` is
+// off, in which case it is kept stopped until the one we follow is gone
+static int global_follow_fork_child = 0;
+static int global_detach_on_fork = 1;
+
+#define MAX_PROCESSES 16
+static pid_t global_held_processes[MAX_PROCESSES];
+static int global_held_count = 0;
+
+// a parent that vforked the process we follow shares its memory, and so our
+// breakpoints, with that process until it execs or exits. the parent is only
+// let go of then, once the breakpoints can be taken out of it
+static pid_t global_vfork_parent = 0;
+
+// new processes can stop before their parent tells us about them. these are
+// the ones that did, which we'll hear about once the parent's stop comes in
+static pid_t global_early_processes[MAX_PROCESSES];
+static int global_early_count = 0;
+
+// checkpoints are copies of the child that were forked off while it was
+// stopped. they stay stopped, and the child can be started over from one of
+// them by forking it again
+#define MAX_CHECKPOINTS 16
+struct checkpoint {
+  pid_t pid;
+  int number;
+};
+static struct checkpoint global_checkpoints[MAX_CHECKPOINTS];
+static int global_checkpoint_count = 0;
+static int global_checkpoint_number = 0;
 
-// this function calls the rust callback with the child's RBP and RIP
-// registers whenever the child is suspended
-void perform_callback(pid_t child)
+// the options that every thread we trace has: stop right before exiting, tell
+// us about new threads, new processes and new programs, and set bit 7 of the
+// signal for system call stops so they can be told apart from other SIGTRAPs
+#define TRACE_OPTIONS (PTRACE_O_TRACEEXIT | PTRACE_O_TRACECLONE | PTRACE_O_TRACEFORK | \
+                       PTRACE_O_TRACEVFORK | PTRACE_O_TRACEEXEC | PTRACE_O_TRACESYSGOOD)
+
+// every thread of the child that we trace. threads that aren't running are
+// `stopped`, `signal` is a signal that the thread received while we were
+// stopping it and is delivered once it is resumed, and `status` is a wait status
+// that it reported at the same time and that we haven't dealt with yet. threads
+// are numbered in the order we saw them, starting at 1. `in_syscall` is set
+// between the stops for entering and leaving a system call
+#define MAX_THREADS 256
+struct thread {
+  pid_t tid;
+  int number;
+  int stopped;
+  int signal;
+  int status;
+  int in_syscall;
+};
+
+static struct thread global_threads[MAX_THREADS];
+static int global_thread_count = 0;
+static int global_thread_number = 0;
+
+// what to do when the child receives a signal: whether to suspend it and hand
+// control to the user, whether to deliver the signal when it is resumed, and
+// whether to tell the user about it. SIGTRAP is not in here since we use it for
+// breakpoints and stepping
+struct signal_policy {
+  int stop;
+  int pass;
+  int print;
+};
+
+static struct signal_policy global_signal_policies[NSIG];
+static int global_signal_policies_ready = 0;
+
+static const struct { const char *name; int number; } signal_names[] = {
+  { "SIGHUP", SIGHUP }, { "SIGINT", SIGINT }, { "SIGQUIT", SIGQUIT },
+  { "SIGILL", SIGILL }, { "SIGTRAP", SIGTRAP }, { "SIGABRT", SIGABRT },
+  { "SIGBUS", SIGBUS }, { "SIGFPE", SIGFPE }, { "SIGKILL", SIGKILL },
+  { "SIGUSR1", SIGUSR1 }, { "SIGSEGV", SIGSEGV }, { "SIGUSR2", SIGUSR2 },
+  { "SIGPIPE", SIGPIPE }, { "SIGALRM", SIGALRM }, { "SIGTERM", SIGTERM },
+  { "SIGSTKFLT", SIGSTKFLT }, { "SIGCHLD", SIGCHLD }, { "SIGCONT", SIGCONT },
+  { "SIGSTOP", SIGSTOP }, { "SIGTSTP", SIGTSTP }, { "SIGTTIN", SIGTTIN },
+  { "SIGTTOU", SIGTTOU }, { "SIGURG", SIGURG }, { "SIGXCPU", SIGXCPU },
+  { "SIGXFSZ", SIGXFSZ }, { "SIGVTALRM", SIGVTALRM }, { "SIGPROF", SIGPROF },
+  { "SIGWINCH", SIGWINCH }, { "SIGIO", SIGIO }, { "SIGPWR", SIGPWR },
+  { "SIGSYS", SIGSYS }
+};
+
+#define SIGNAL_NAME_COUNT (sizeof(signal_names) / sizeof(signal_names[0]))
+
+// this function sets up the default signal policies. signals that programs
+// use in their normal operation are passed on silently, SIGINT is the user
+// interrupting the program so it isn't passed on, and everything else is
+// treated as an exception
+static void init_signal_policies()
 {
-  struct user_regs_struct regs;
-  memset(&regs, 0, sizeof(regs));
+  if (global_signal_policies_ready) return;
+  global_signal_policies_ready = 1;
+
+  for (int i = 1; i < NSIG; ++i) {
+    global_signal_policies[i].stop = 1;
+    global_signal_policies[i].pass = 1;
+    global_signal_policies[i].print = 1;
+  }
+
+  int quiet[] = { SIGALRM, SIGURG, SIGCHLD, SIGWINCH, SIGIO, SIGVTALRM, SIGPROF };
+  for (size_t i = 0; i < sizeof(quiet) / sizeof(quiet[0]); ++i) {
+    global_signal_policies[quiet[i]].stop = 0;
+    global_signal_policies[quiet[i]].print = 0;
+  }
+
+  global_signal_policies[SIGINT].pass = 0;
+}
+
+// this function returns the name of a signal, e.g "SIGSEGV"
+static const char *signal_name(int number)
+{
+  static char name[16];
+  for (size_t i = 0; i < SIGNAL_NAME_COUNT; ++i)
+    if (signal_names[i].number == number) return signal_names[i].name;
+
+  snprintf(name, sizeof(name), "SIG%d", number);
+  return name;
+}
+
+// this function finds a signal by its name, with or without the "SIG" prefix,
+// or by its number
+// returns -1 if there is no such signal
+static int signal_number(const char *name)
+{
+  char *end;
+  long number = strtol(name, &end, 10);
+  if (*name && *end == '\0') return number > 0 && number < NSIG ? number : -1;
+
+  if (strncasecmp(name, "SIG", 3) == 0) name += 3;
+  for (size_t i = 0; i < SIGNAL_NAME_COUNT; ++i)
+    if (strcasecmp(signal_names[i].name + 3, name) == 0) return signal_names[i].number;
+
+  return -1;
+}
+
+// this function reads the general purpose registers of the child
+int get_regs(pid_t child, struct user_regs_struct *regs)
+{
+  memset(regs, 0, sizeof(*regs));
   struct iovec iov;
-  iov.iov_base = &regs;
-  iov.iov_len = sizeof(regs);
+  iov.iov_base = regs;
+  iov.iov_len = sizeof(*regs);
 
   long r = ptrace(PTRACE_GETREGSET, child, NT_PRSTATUS, &iov);
   if (r == -1) {
     printf("PTRACE_GETREGSET failed: %s\n", strerror(errno));
-    return;
+    return -1;
   }
 
-  // not sure why rbp needs to be offset by 16. this is probably not portable
-  global_cb(global_scope, global_types, regs.rbp + (2 * sizeof(long)), regs.rip);
+  return 0;
 }
 
-// this function gets called in the child process after forking
-// it tells the parent to trace it and then execves the target
-void setup_inferior(const char *target, char *argv[])
+// this function writes the general purpose registers of the child
+int set_regs(pid_t child, struct user_regs_struct *regs)
 {
-  ptrace(PTRACE_TRACEME, 0, NULL, NULL);
-  execv(target, argv);
+  struct iovec iov;
+  iov.iov_base = regs;
+  iov.iov_len = sizeof(*regs);
+
+  long r = ptrace(PTRACE_SETREGSET, child, NT_PRSTATUS, &iov);
+  if (r == -1) {
+    printf("PTRACE_SETREGSET failed: %s\n", strerror(errno));
+    return -1;
+  }
+
+  return 0;
 }
 
-// this function attaches to the child process and begins tracing it
-// we are ignoring SIGTRAP because it is sent when the child calls execve
-// which should not start the debugger
-void attach_to_inferior(pid_t child) {
-  while (1) {
-    int status;
-    waitpid(child, &status, 0);
+// this function overwrites a single byte in the child's memory and
+// returns the byte that was there before
+// ptrace can only read and write whole words, so we read the word
+// containing the byte, patch it and write it back
+int poke_byte(pid_t child, uintptr_t address, uint8_t byte, uint8_t *old_byte)
+{
+  errno = 0;
+  long word = ptrace(PTRACE_PEEKDATA, child, (void *)address, NULL);
+  if (word == -1L && errno != 0) {
+    printf("PTRACE_PEEKDATA failed: %s\n", strerror(errno));
+    return -1;
+  }
 
-    if (WIFSTOPPED(status) && WSTOPSIG(status) == SIGTRAP)
-      ptrace(PTRACE_CONT, child, NULL, NULL);
-    else if (WIFEXITED(status)) {
-      printf("Child process exited\n");
-      return;
-    } else {
-      global_child = child;
-      perform_callback(child);
-      return;
+  if (old_byte) *old_byte = *(uint8_t *)&word;
+  *(uint8_t *)&word = byte;
+
+  if (ptrace(PTRACE_POKEDATA, child, (void *)address, (void *)word) == -1) {
+    printf("PTRACE_POKEDATA failed: %s\n", strerror(errno));
+    return -1;
+  }
+
+  return 0;
+}
+
+// this function finds the active breakpoint at `address`, if there is one
+static struct breakpoint *find_breakpoint(uintptr_t address)
+{
+  for (int i = 0; i < MAX_BREAKPOINTS; ++i)
+    if (global_breakpoints[i].active && global_breakpoints[i].address == address)
+      return &global_breakpoints[i];
+
+  return NULL;
+}
+
+// this function writes an int3 at `address` and records it in a free slot
+// of the breakpoint table
+static struct breakpoint *insert_breakpoint(uintptr_t address, int number)
+{
+  struct breakpoint *bp = NULL;
+  for (int i = 0; i < MAX_BREAKPOINTS && bp == NULL; ++i)
+    if (!global_breakpoints[i].active) bp = &global_breakpoints[i];
+
+  if (bp == NULL) {
+    printf("cannot set more than %d breakpoints\n", MAX_BREAKPOINTS);
+    return NULL;
+  }
+
+  if (poke_byte(global_child, address, INT3, &bp->original_byte) == -1)
+    return NULL;
+
+  bp->address = address;
+  bp->number = number;
+  bp->active = 1;
+  bp->inserted = 1;
+
+  return bp;
+}
+
+// this function checks whether the child stopped because it executed one of
+// our int3 instructions. if it did, the breakpoint is lifted and the
+// instruction pointer is moved back onto the instruction we overwrote so
+// that the child can carry on as if nothing happened
+// if the child was single-stepped (`stepped`), it doesn't execute the int3 but
+// stops right on top of it, which counts as hitting it too
+// returns the number of the breakpoint that was hit (0 for temporary
+// breakpoints) or -1 if the child did not hit a breakpoint
+static int handle_breakpoint(pid_t child, int stepped)
+{
+  struct user_regs_struct regs;
+  if (get_regs(child, &regs) == -1) return -1;
+
+  if (stepped) {
+    struct breakpoint *bp = find_breakpoint(regs.rip);
+    if (bp == NULL || !bp->inserted) return -1;
+
+    if (bp->number == 0) {
+      poke_byte(child, bp->address, bp->original_byte, NULL);
+      bp->inserted = 0;
+      bp->active = 0;
     }
+    return bp->number;
   }
+
+  // a single step that ends right after a one-byte instruction with a
+  // breakpoint on it looks the same, but the kernel tells them apart
+  siginfo_t siginfo;
+  if (ptrace(PTRACE_GETSIGINFO, child, NULL, &siginfo) == -1 || siginfo.si_code != SI_KERNEL)
+    return -1;
+
+  // the CPU has already executed the int3, so RIP points one byte past it
+  struct breakpoint *bp = find_breakpoint(regs.rip - 1);
+  if (bp == NULL || !bp->inserted) return -1;
+
+  int number = bp->number;
+  poke_byte(child, bp->address, bp->original_byte, NULL);
+  bp->inserted = 0;
+  if (bp->number == 0) bp->active = 0;
+
+  regs.rip = bp->address;
+  set_regs(child, &regs);
+
+  return number;
 }
 
-#endif
+// this function writes debug register `index` of thread `tid`
+static int set_debug_register(pid_t tid, int index, unsigned long value)
+{
+  size_t offset = offsetof(struct user, u_debugreg) + index * sizeof(long);
+  if (ptrace(PTRACE_POKEUSER, tid, (void *)offset, (void *)value) == -1) {
+    printf("PTRACE_POKEUSER failed for DR%d: %s\n", index, strerror(errno));
+    return -1;
+  }
 
-// this function initializes global state and starts the child process
-void setup(const char *target, exc_callback cb, void *scope, void *types)
+  return 0;
+}
+
+// this function reads debug register `index` of thread `tid`
+static unsigned long get_debug_register(pid_t tid, int index)
 {
-  global_cb = cb;
-  global_scope = scope;
-  global_types = types;
+  size_t offset = offsetof(struct user, u_debugreg) + index * sizeof(long);
+  return ptrace(PTRACE_PEEKUSER, tid, (void *)offset, NULL);
+}
 
-  pid_t child = 0;
+// this function programs the watchpoint table into the debug registers of
+// thread `tid`. for each watchpoint, DR7 has an enable bit (bit 2 * i) and
+// four bits at 16 + 4 * i: two for the kind of access (01 for writes, 11 for
+// reads and writes -- x86 can't watch only reads), and two for the size (00,
+// 01, 11 and 10 for 1, 2, 4 and 8 bytes)
+static int write_thread_debug_registers(pid_t tid)
+{
+  unsigned long dr7 = 0;
+  for (int i = 0; i < MAX_WATCHPOINTS; ++i) {
+    struct watchpoint *wp = &global_watchpoints[i];
+    if (!wp->active) continue;
 
-#ifdef __APPLE__
-  posix_spawnattr_t attr;
-  posix_spawnattr_init(&attr);
-  posix_spawnattr_setflags(&attr, 0x100); // disable ASLR on MacOS
-  posix_spawnp(&child, target, NULL, &attr, NULL, NULL);
+    unsigned long rw = wp->kind == WATCH_WRITE ? 1 : 3;
+    unsigned long len = wp->size == 1 ? 0 : wp->size == 2 ? 1 : wp->size == 4 ? 3 : 2;
+    dr7 |= 1UL << (2 * i);
+    dr7 |= (rw | (len << 2)) << (16 + 4 * i);
 
-  mach_port_t task;
-  mach_port_t task_exception_port;
-  kern_return_t kret;
+    if (set_debug_register(tid, i, wp->address) == -1) return -1;
+  }
 
-  kret = task_for_pid(mach_task_self(), child, &task);
-  if (kret != KERN_SUCCESS) {
-    printf("task_for_pid failed: %s\n", mach_error_string(kret));
-    return;
+  return set_debug_register(tid, 7, dr7);
+}
+
+// this function programs the watchpoint table into the debug registers of
+// every thread of the child, since each thread has its own
+static int write_debug_registers()
+{
+  for (int t = 0; t < global_thread_count; ++t)
+    if (write_thread_debug_registers(global_threads[t].tid) == -1) return -1;
+
+  return 0;
+}
+
+// this function checks DR6 for watchpoints that have fired, i.e whether the
+// last SIGTRAP came from one
+static int watchpoint_triggered(pid_t child)
+{
+  unsigned long dr6 = get_debug_register(child, 6);
+  for (int i = 0; i < MAX_WATCHPOINTS; ++i)
+    if (global_watchpoints[i].active && (dr6 & (1UL << i))) return 1;
+
+  return 0;
+}
+
+// this function finds the watchpoint that made the child trap, and clears
+// DR6 since the CPU never does
+// returns the number of the watchpoint, or -1 if no watchpoint fired
+static int handle_watchpoint(pid_t child)
+{
+  unsigned long dr6 = get_debug_register(child, 6);
+  if ((dr6 & 0xF) == 0) return -1;
+
+  set_debug_register(child, 6, 0);
+  for (int i = 0; i < MAX_WATCHPOINTS; ++i)
+    if (global_watchpoints[i].active && (dr6 & (1UL << i))) return global_watchpoints[i].number;
+
+  return -1;
+}
+
+// this function moves the child past a breakpoint that it is sitting on,
+// if there is one. the breakpoint has to be lifted for the child to execute
+// the original instruction, so we step over that one instruction and then
+// put the int3 back. `sig` points to the signal that the child should receive,
+// which it gets during the step instead, so it is cleared
+// returns 1 if the step was interrupted by something other than the usual
+// SIGTRAP (e.g the instruction faulted, a watchpoint fired or the child
+// forked), in which case `status` holds the new stop status for the caller to
+// deal with
+static int step_over_breakpoint(pid_t child, int *sig, int *status)
+{
+  struct user_regs_struct regs;
+  if (get_regs(child, &regs) == -1) return 0;
+
+  struct breakpoint *bp = find_breakpoint(regs.rip);
+  if (bp == NULL) return 0;
+
+  if (bp->inserted) {
+    poke_byte(child, bp->address, bp->original_byte, NULL);
+    bp->inserted = 0;
   }
 
-  kret = mach_port_allocate(mach_task_self(), MACH_PORT_RIGHT_RECEIVE, &task_exception_port);
-  if (kret != KERN_SUCCESS) {
-    printf("mach_port_allocate failed: %s\n", mach_error_string(kret));
+  ptrace(PTRACE_SINGLESTEP, child, NULL, (void *)(long)*sig);
+  *sig = 0;
+  waitpid(child, status, __WALL);
+
+  if (!WIFSTOPPED(*status)) return 1;
+
+  poke_byte(child, bp->address, INT3, NULL);
+  bp->inserted = 1;
+
+  return WSTOPSIG(*status) != SIGTRAP || *status >> 16 != 0 || watchpoint_triggered(child);
+}
+
+// this function executes a single instruction in the child and waits for
+// it to stop again
+static void step_instruction(pid_t child, int sig, int *status)
+{
+  struct user_regs_struct regs;
+  if (get_regs(child, &regs) == -1) return;
+
+  // if there is a breakpoint here, stepping over it is all we need to do
+  if (find_breakpoint(regs.rip)) {
+    step_over_breakpoint(child, &sig, status);
     return;
   }
 
-  kret = mach_port_insert_right(
-    mach_task_self(),
-    task_exception_port,
-    task_exception_port,
-    MACH_MSG_TYPE_MAKE_SEND
-    );
-  if (kret != KERN_SUCCESS) {
-    printf("mach_port_insert_right failed: %s\n", mach_error_string(kret));
-    return;
+  ptrace(PTRACE_SINGLESTEP, child, NULL, (void *)(long)sig);
+  waitpid(child, status, __WALL);
+}
+
+// this function finds out why the child was stopped by signal `signo`
+// returns 0 on success and -1 on failure
+static int get_stop_info(pid_t child, int signo, struct stop_info *info)
+{
+  memset(info, 0, sizeof(*info));
+  info->signo = signo;
+
+  siginfo_t siginfo;
+  if (ptrace(PTRACE_GETSIGINFO, child, NULL, &siginfo) == -1) {
+    printf("PTRACE_GETSIGINFO failed: %s\n", strerror(errno));
+    return -1;
   }
+  info->code = siginfo.si_code;
 
-  kret = task_set_exception_ports(
-    task,
-    EXC_MASK_ALL,
-    task_exception_port,
-    EXCEPTION_STATE_IDENTITY | MACH_EXCEPTION_CODES,
-    x86_THREAD_STATE64
-    );
-  if (kret != KERN_SUCCESS) {
-    printf("task_set_exception_ports failed: %s\n", mach_error_string(kret));
-    return;
+  // a positive code means the kernel raised the signal because of something
+  // the child did. otherwise someone sent it, e.g with kill()
+  if (siginfo.si_code > 0 && siginfo.si_code != SI_KERNEL) {
+    if (signo == SIGSEGV || signo == SIGBUS || signo == SIGILL || signo == SIGFPE)
+      info->address = (uintptr_t)siginfo.si_addr;
+  } else if (siginfo.si_code <= 0) {
+    info->sender = siginfo.si_pid;
   }
 
-  global_task = task;
-  global_task_exc = task_exception_port;
+  // ud2 (0x0F 0x0B) is what e.g __builtin_trap compiles to
+  if (signo == SIGILL) {
+    errno = 0;
+    long instruction = ptrace(PTRACE_PEEKDATA, child, info->address, NULL);
+    info->trap = errno == 0 && (instruction & 0xFFFF) == 0x0B0F;
+  }
 
-  size_t req_size = sizeof(union __RequestUnion__mach_exc_subsystem);
-  size_t rep_size = sizeof(union __ReplyUnion__mach_exc_subsystem);
-  mach_msg_server_once(
-    mach_exc_server,
-    req_size > rep_size ? req_size : rep_size,
-    task_exception_port,
-    0
+  return 0;
+}
+
+// this function finds the base of the stack frame that the rust side reads
+// local variables from
+static uintptr_t frame_base(const struct user_regs_struct *regs)
+{
+  // not sure why rbp needs to be offset by 16. this is probably not portable
+  return regs->rbp + (2 * sizeof(long));
+}
+
+// this function calls the rust callback with the child's frame base and RIP
+// whenever the child is suspended. `info` says which signal
+// suspended the child, and can be NULL if it wasn't a signal
+int perform_callback(pid_t child, int reason, const struct stop_info *info)
+{
+  struct user_regs_struct regs;
+  if (get_regs(child, &regs) == -1) return ACTION_KILL;
+  global_thread = child;
+
+  struct stop_info no_signal = { 0 };
+  if (info == NULL) info = &no_signal;
+
+  // whatever we printed has to show up before the console does
+  fflush(stdout);
+
+  // pressing Ctrl-C while the user is in the console doesn't interrupt
+  // anything. system call stops only go to the console once they're caught
+  int console = reason != STOP_SYSCALL_ENTRY && reason != STOP_SYSCALL_EXIT;
+  if (console) global_child_running = 0;
+
+  int action = global_cb(global_session, frame_base(&regs), regs.rip, reason, info);
+  if (console) {
+    global_interrupted = 0;
+    global_child_running = 1;
+  }
+
+  return action;
+}
+
+// this function finds the entry for thread `tid` in the thread table
+// returns its index, or -1 if we don't know about the thread
+static int find_thread(pid_t tid)
+{
+  for (int i = 0; i < global_thread_count; ++i)
+    if (global_threads[i].tid == tid) return i;
+
+  return -1;
+}
+
+// this function adds thread `tid` to the thread table
+// returns its index, or -1 if the table is full
+static int add_thread(pid_t tid)
+{
+  if (global_thread_count == MAX_THREADS) return -1;
+
+  struct thread *thread = &global_threads[global_thread_count];
+  memset(thread, 0, sizeof(*thread));
+  thread->tid = tid;
+  thread->number = ++global_thread_number;
+
+  return global_thread_count++;
+}
+
+// this function removes thread `tid` from the thread table once it has exited
+static void remove_thread(pid_t tid)
+{
+  int index = find_thread(tid);
+  if (index == -1) return;
+
+  --global_thread_count;
+  memmove(
+    &global_threads[index],
+    &global_threads[index + 1],
+    (global_thread_count - index) * sizeof(struct thread)
     );
-#elif __linux__
-  do {
-    child = fork();
-    switch (child) {
-    case 0:
-      setup_inferior(target, NULL);
-      break;
-    case -1:
-      break;
-    default:
-      attach_to_inferior(child);
-      break;
-    }
-  } while (child == -1 && errno == EAGAIN);
-#endif
+  if (global_thread == tid) global_thread = global_child;
 }
 
-// this function reads the target process's memory into a buffer
-// in the parent/tracing process
-// `address` is the location in the target process's address space
-void read_addr(void *buffer, uintptr_t address, size_t size)
+// this function checks whether `tid` is a thread of the child, rather than a
+// process that the child forked
+static int is_our_thread(pid_t tid)
 {
-#ifdef __APPLE__
-  kern_return_t kret;
-  mach_vm_size_t local_size = size;
-  kret = mach_vm_read_overwrite(global_task, address, (mach_vm_size_t)size, (mach_vm_address_t)buffer, &local_size);
-  if (kret != KERN_SUCCESS) {
-    printf("mach_vm_read failed: %s\n", mach_error_string(kret));
-    memset(buffer, 0, size);
-    return;
+  return syscall(SYS_tgkill, global_child, tid, 0) == 0;
+}
+
+// this function makes a note of a new process that stopped before the fork
+// that created it was reported to us
+static void remember_early_process(pid_t pid)
+{
+  if (global_early_count < MAX_PROCESSES) global_early_processes[global_early_count++] = pid;
+}
+
+// this function checks whether new process `pid` has already stopped, and
+// forgets about it if it has
+static int take_early_process(pid_t pid)
+{
+  for (int i = 0; i < global_early_count; ++i) {
+    if (global_early_processes[i] != pid) continue;
+
+    global_early_processes[i] = global_early_processes[--global_early_count];
+    return 1;
   }
-#elif __linux__
 
-  // stolen shamelessly from https://github.com/scanmem/scanmem/blob/master/ptrace.c
+  return 0;
+}
 
-#ifdef __GNUC__
-#define UNLIKELY(x)   __builtin_expect(!!(x), 0)
-#else
-#define UNLIKELY(x)   (x)
+// this function lists the threads of process `pid` into `tids`
+// returns the number of threads
+static int list_threads(pid_t pid, pid_t *tids, int max)
+{
+  char path[64];
+  snprintf(path, sizeof(path), "/proc/%d/task", pid);
+  DIR *dir = opendir(path);
+  if (dir == NULL) return 0;
+
+  int count = 0;
+  struct dirent *entry;
+  while ((entry = readdir(dir)) != NULL && count < max) {
+    pid_t tid = atoi(entry->d_name);
+    if (tid > 0) tids[count++] = tid;
+  }
+  closedir(dir);
+
+  return count;
+}
+
+// this function checks whether a thread that just stopped with SIGTRAP executed
+// one of our int3 instructions. if it did, the instruction pointer is moved back
+// onto the int3, so that the thread hits the breakpoint again once it is resumed
+// returns 1 if the thread was at a breakpoint
+static int rewind_breakpoint(pid_t tid)
+{
+  siginfo_t siginfo;
+  struct user_regs_struct regs;
+  if (ptrace(PTRACE_GETSIGINFO, tid, NULL, &siginfo) == -1 || siginfo.si_code != SI_KERNEL)
+    return 0;
+  if (get_regs(tid, &regs) == -1) return 0;
+
+  struct breakpoint *bp = find_breakpoint(regs.rip - 1);
+  if (bp == NULL || !bp->inserted) return 0;
+
+  regs.rip = bp->address;
+  set_regs(tid, &regs);
+
+  return 1;
+}
+
+// this function stops every thread of the child other than `current`, which
+// is already stopped, so that the user sees the whole process standing still
+// threads can have something else to report before our SIGSTOP reaches them.
+// that is saved in the thread table and dealt with after the user is done,
+// except for breakpoints, which the thread will simply hit again
+static void stop_other_threads(pid_t current)
+{
+  for (int i = 0; i < global_thread_count; ++i) {
+    struct thread *thread = &global_threads[i];
+    if (thread->tid != current && !thread->stopped)
+      syscall(SYS_tgkill, global_child, thread->tid, SIGSTOP);
+  }
+
+  while (1) {
+    int running = 0;
+    for (int i = 0; i < global_thread_count; ++i)
+      if (!global_threads[i].stopped) running = 1;
+    if (!running) break;
+
+    // the main thread can't be waited on until every other thread is gone, so
+    // we take the threads in whatever order they stop
+    int status;
+    pid_t tid = waitpid(-1, &status, __WALL);
+    if (tid == -1) break;
+
+    int index = find_thread(tid);
+    if (index == -1) {
+      if (!is_our_thread(tid)) {
+        remember_early_process(tid);
+        continue;
+      }
+
+      // a new thread that we haven't been told about yet, stopped by the
+      // SIGSTOP that every thread starts with
+      index = add_thread(tid);
+      if (index == -1) continue;
+      write_thread_debug_registers(tid);
+    }
+
+    struct thread *thread = &global_threads[index];
+    thread->stopped = 1;
+    if (WIFSTOPPED(status) && WSTOPSIG(status) == SIGSTOP && status >> 16 == 0) continue;
+    if (WIFSTOPPED(status) && WSTOPSIG(status) == SIGTRAP && rewind_breakpoint(tid)) continue;
+    thread->status = status;
+  }
+}
+
+// this function resumes every stopped thread of the child, except for the ones
+// that have something left to report
+static void resume_threads()
+{
+  for (int i = 0; i < global_thread_count; ++i) {
+    struct thread *thread = &global_threads[i];
+    if (!thread->stopped || thread->status) continue;
+
+    int request = global_syscall_tracing ? PTRACE_SYSCALL : PTRACE_CONT;
+    ptrace(request, thread->tid, NULL, (void *)(long)thread->signal);
+    thread->signal = 0;
+    thread->stopped = 0;
+  }
+}
+
+// this function waits until a thread of the child has something to report,
+// starting with what the threads reported while we were stopping them
+// returns the id of the thread
+static pid_t wait_for_thread(int *status)
+{
+  for (int i = 0; i < global_thread_count; ++i) {
+    struct thread *thread = &global_threads[i];
+    if (thread->status == 0) continue;
+
+    *status = thread->status;
+    thread->status = 0;
+    return thread->tid;
+  }
+
+  return waitpid(-1, status, __WALL);
+}
+
+// this function finds out whether thread `tid` is entering or leaving a system
+// call. older kernels can't tell us, in which case the stops alternate
+// returns 1 when it is entering one
+static int entering_syscall(pid_t tid)
+{
+  int index = find_thread(tid);
+  if (index == -1) return 0;
+
+  struct thread *thread = &global_threads[index];
+  struct ptrace_syscall_info info;
+  if (ptrace(PTRACE_GET_SYSCALL_INFO, tid, (void *)sizeof(info), &info) > 0)
+    thread->in_syscall = info.op == PTRACE_SYSCALL_INFO_ENTRY;
+  else
+    thread->in_syscall = !thread->in_syscall;
+
+  return thread->in_syscall;
+}
+
+// this function is our SIGINT handler. a child that doesn't get the SIGINT
+// from the terminal, e.g because it runs on its own terminal or we attached to
+// it, is stopped with a SIGSTOP instead
+static void interrupt_handler(int signo)
+{
+  global_interrupted = 1;
+  if (global_child_running && !global_shares_terminal) kill(global_child, SIGSTOP);
+}
+
+// this function checks whether the child's SIGSTOP came from our SIGINT
+// handler
+static int sent_by_interrupt(pid_t tid)
+{
+  siginfo_t siginfo;
+  if (ptrace(PTRACE_GETSIGINFO, tid, NULL, &siginfo) == -1) return 0;
+
+  return siginfo.si_code == SI_USER && siginfo.si_pid == getpid();
+}
+
+// this function checks whether the child's SIGSTOP came from us, i.e it is
+// left over from stopping the other threads
+static int sent_by_us(pid_t tid)
+{
+  siginfo_t siginfo;
+  if (ptrace(PTRACE_GETSIGINFO, tid, NULL, &siginfo) == -1) return 0;
+
+  return siginfo.si_code == SI_TKILL && siginfo.si_pid == getpid();
+}
+
+// this function keeps track of threads starting and exiting, which only
+// concerns us and not the user
+// returns 1 if `status` was one of those events, in which case thread `tid` is
+// ready to be resumed
+static int handle_thread_event(pid_t tid, int status)
+{
+  int event = status >> 16;
+  if (find_thread(tid) == -1 && !is_our_thread(tid)) {
+    // a new process, which stays stopped until its parent's fork comes in
+    remember_early_process(tid);
+  } else if (find_thread(tid) == -1) {
+    // a new thread that reported its first stop before the thread that
+    // created it did
+    int index = add_thread(tid);
+    if (index == -1) return 1;
+    global_threads[index].stopped = 1;
+    write_thread_debug_registers(tid);
+  } else if (event == PTRACE_EVENT_CLONE) {
+    unsigned long new_tid;
+    ptrace(PTRACE_GETEVENTMSG, tid, NULL, &new_tid);
+    if (find_thread(new_tid) != -1) return 1;
+
+    // the new thread is traced from the start and stops with a SIGSTOP
+    // before it runs. it's resumed along with everything else
+    int index = add_thread(new_tid);
+    if (index == -1) return 1;
+
+    int new_status;
+    waitpid(new_tid, &new_status, __WALL);
+    global_threads[index].stopped = 1;
+    write_thread_debug_registers(new_tid);
+  } else if (event == PTRACE_EVENT_EXIT && tid != global_child) {
+    // threads other than the main one exit on their own
+  } else {
+    return 0;
+  }
+
+  return 1;
+}
+
+// this function lets go of process `pid` after a fork, when we're following
+// the other process. `tids` are its threads, which are all stopped. unless
+// we're supposed to keep it, the process is detached from, and our
+// breakpoints and watchpoints are taken out of it so that it doesn't trip
+// over them. a vforked child shares its parent's memory until it execs, so if
+// `shared_memory` is set the breakpoints have to stay
+static void release_process(pid_t pid, const pid_t *tids, int count, int shared_memory)
+{
+  if (!global_detach_on_fork && global_held_count < MAX_PROCESSES) {
+    global_held_processes[global_held_count++] = pid;
+    printf("Process %d is kept stopped until process %d exits\n", pid, global_child);
+    fflush(stdout);
+    return;
+  }
+
+  printf("Detaching from process %d after fork\n", pid);
+  fflush(stdout);
+
+  for (int i = 0; i < MAX_BREAKPOINTS && !shared_memory; ++i) {
+    struct breakpoint *bp = &global_breakpoints[i];
+    if (bp->active && bp->inserted) poke_byte(pid, bp->address, bp->original_byte, NULL);
+  }
+
+  for (int i = 0; i < count; ++i) {
+    set_debug_register(tids[i], 7, 0);
+    ptrace(PTRACE_DETACH, tids[i], NULL, NULL);
+  }
+}
+
+// this function deals with thread `tid` of the child forking. the new process
+// is traced from the start and stops before it runs. either the child or the
+// new process is followed from then on, and the other is let go of
+// returns the process that we follow
+static pid_t handle_fork(pid_t tid, int vfork)
+{
+  unsigned long new_pid;
+  ptrace(PTRACE_GETEVENTMSG, tid, NULL, &new_pid);
+
+  int status;
+  if (!take_early_process(new_pid)) waitpid(new_pid, &status, __WALL);
+
+  if (!global_follow_fork_child) {
+    pid_t tids[1] = { new_pid };
+    release_process(new_pid, tids, 1, vfork);
+    return global_child;
+  }
+
+  // every thread of the parent has to be stopped before we can let go of it
+  stop_other_threads(tid);
+  pid_t tids[MAX_THREADS];
+  for (int i = 0; i < global_thread_count; ++i) tids[i] = global_threads[i].tid;
+  pid_t parent = global_child;
+  global_child = new_pid;
+  if (vfork && global_detach_on_fork) global_vfork_parent = parent;
+  else release_process(parent, tids, global_thread_count, 0);
+
+  // the new process only has the thread that forked
+  printf("Following process %d after fork\n", (int)new_pid);
+  fflush(stdout);
+  global_thread = new_pid;
+  global_thread_count = 0;
+  global_thread_number = 0;
+  global_threads[add_thread(new_pid)].stopped = 1;
+  write_thread_debug_registers(new_pid);
+
+  return new_pid;
+}
+
+// this function lets go of the parent that vforked the process we follow, if
+// it is still waiting for that process to exec or exit
+static void release_vfork_parent()
+{
+  if (global_vfork_parent == 0) return;
+
+  pid_t tids[MAX_THREADS];
+  int count = list_threads(global_vfork_parent, tids, MAX_THREADS);
+  release_process(global_vfork_parent, tids, count, 0);
+  global_vfork_parent = 0;
+}
+
+// this function starts following process `pid`, which is stopped, instead of
+// the child
+static void follow_process(pid_t pid)
+{
+  global_child = pid;
+  global_thread = pid;
+  global_thread_count = 0;
+  global_thread_number = 0;
+
+  pid_t tids[MAX_THREADS];
+  int count = list_threads(pid, tids, MAX_THREADS);
+  for (int i = 0; i < count; ++i) {
+    int index = add_thread(tids[i]);
+    if (index != -1) global_threads[index].stopped = 1;
+  }
+  write_debug_registers();
+}
+
+// this function starts following the process that was kept stopped most
+// recently, once the one we were following is gone
+// returns its pid
+static pid_t switch_to_held_process()
+{
+  pid_t pid = global_held_processes[--global_held_count];
+  follow_process(pid);
+
+  return pid;
+}
+
+// this function looks up `type` (one of the AT_* constants) in the auxiliary
+// vector that the kernel gave process `pid` when it started its program
+// returns 0 if it isn't there
+static uintptr_t read_auxv(pid_t pid, unsigned long type)
+{
+  char path[64];
+  snprintf(path, sizeof(path), "/proc/%d/auxv", pid);
+  int fd = open(path, O_RDONLY);
+  if (fd == -1) return 0;
+
+  uintptr_t value = 0;
+  Elf64_auxv_t entry;
+  while (read(fd, &entry, sizeof(entry)) == sizeof(entry) && entry.a_type != AT_NULL)
+    if (entry.a_type == type) value = entry.a_un.a_val;
+  close(fd);
+
+  return value;
+}
+
+// this function works out the load bias of the executable that process `pid`
+// is running, from its entry point in the ELF header and the one the kernel
+// jumped to (AT_ENTRY in the auxiliary vector)
+static uintptr_t compute_load_bias(pid_t pid)
+{
+  char path[64];
+  snprintf(path, sizeof(path), "/proc/%d/exe", pid);
+  Elf64_Ehdr header;
+  int fd = open(path, O_RDONLY);
+  int valid = fd != -1 && read(fd, &header, sizeof(header)) == sizeof(header);
+  if (fd != -1) close(fd);
+
+  // anything else is loaded where it was linked
+  if (!valid || header.e_type != ET_DYN) return 0;
+
+  uintptr_t entry = read_auxv(pid, AT_ENTRY);
+  return entry ? entry - header.e_entry : 0;
+}
+
+// this function finds the dynamic symbols `names` in the ELF file at `path`
+// and stores their values in `values`, which are left alone for the ones that
+// aren't there
+static void find_dynamic_symbols(const char *path, const char *names[], uintptr_t values[], int count)
+{
+  int fd = open(path, O_RDONLY);
+  if (fd == -1) return;
+
+  Elf64_Ehdr header;
+  Elf64_Shdr *sections = NULL;
+  Elf64_Sym *symbols = NULL;
+  char *strings = NULL;
+  if (pread(fd, &header, sizeof(header), 0) != sizeof(header)) goto done;
+
+  size_t size = header.e_shnum * sizeof(Elf64_Shdr);
+  sections = malloc(size);
+  if (pread(fd, sections, size, header.e_shoff) != (ssize_t)size) goto done;
+
+  for (int i = 0; i < header.e_shnum; ++i) {
+    if (sections[i].sh_type != SHT_DYNSYM || sections[i].sh_link >= header.e_shnum) continue;
+
+    // the names of the symbols are in the string table that it links to
+    Elf64_Shdr *table = &sections[sections[i].sh_link];
+    symbols = malloc(sections[i].sh_size);
+    strings = malloc(table->sh_size + 1);
+    if (pread(fd, symbols, sections[i].sh_size, sections[i].sh_offset) != (ssize_t)sections[i].sh_size ||
+        pread(fd, strings, table->sh_size, table->sh_offset) != (ssize_t)table->sh_size)
+      goto done;
+    strings[table->sh_size] = '\0';
+
+    for (size_t j = 0; j < sections[i].sh_size / sizeof(Elf64_Sym); ++j) {
+      if (symbols[j].st_name >= table->sh_size) continue;
+      for (int k = 0; k < count; ++k)
+        if (strcmp(strings + symbols[j].st_name, names[k]) == 0) values[k] = symbols[j].st_value;
+    }
+    break;
+  }
+
+done:
+  free(sections);
+  free(symbols);
+  free(strings);
+  close(fd);
+}
+
+// this function finds the dynamic linker of process `pid`, which has just
+// started its program, and sets the breakpoint that tells us when it has
+// loaded or unloaded shared libraries. statically linked programs have no
+// dynamic linker
+static void watch_shared_libraries(pid_t pid)
+{
+  global_r_debug = 0;
+
+  // the kernel loaded the dynamic linker at AT_BASE. its file is the one that
+  // is mapped there
+  uintptr_t base = read_auxv(pid, AT_BASE);
+  if (base == 0) return;
+
+  char path[PATH_MAX + 128];
+  snprintf(path, sizeof(path), "/proc/%d/maps", pid);
+  FILE *maps = fopen(path, "r");
+  if (maps == NULL) return;
+
+  int found = 0;
+  char line[PATH_MAX + 128];
+  while (!found && fgets(line, sizeof(line), maps)) {
+    uintptr_t start;
+    found = sscanf(line, "%lx-%*x %*s %*x %*s %*d %s", &start, path) == 2 && start == base;
+  }
+  fclose(maps);
+  if (!found) return;
+
+  const char *names[2] = { "_r_debug", "_dl_debug_state" };
+  uintptr_t values[2] = { 0, 0 };
+  find_dynamic_symbols(path, names, values, 2);
+  if (values[0] == 0 || values[1] == 0) return;
+
+  global_r_debug = base + values[0];
+  if (find_breakpoint(base + values[1]) == NULL) insert_breakpoint(base + values[1], SHLIB_BREAKPOINT);
+}
+
+// this function finds out where the child's executable was loaded. a new run
+// of a position-independent executable can be loaded somewhere else than the
+// last one, so the breakpoints are moved along with it
+static void update_load_bias(pid_t child)
+{
+  uintptr_t bias = compute_load_bias(child);
+  for (int i = 0; i < MAX_BREAKPOINTS; ++i)
+    global_breakpoints[i].address += bias - global_load_bias;
+
+  global_load_bias = bias;
+}
+
+// this function forgets about everything that belonged to the program that
+// the child was running before it called execve: its other threads are gone,
+// and so is the memory that breakpoints and watchpoints were in
+static void forget_old_image()
+{
+  for (int i = 0; i < MAX_BREAKPOINTS; ++i) {
+    global_breakpoints[i].active = 0;
+    global_breakpoints[i].inserted = 0;
+  }
+  for (int i = 0; i < MAX_WATCHPOINTS; ++i) global_watchpoints[i].active = 0;
+  global_load_bias = compute_load_bias(global_child);
+
+  global_thread = global_child;
+  global_thread_count = 0;
+  global_thread_number = 0;
+  global_threads[add_thread(global_child)].stopped = 1;
+}
+
+// this function removes all breakpoints from the child and stops tracing it
+// all of its threads have to be stopped for this to work
+static void detach_from_inferior(pid_t child)
+{
+  release_vfork_parent();
+  for (int i = 0; i < MAX_BREAKPOINTS; ++i) {
+    struct breakpoint *bp = &global_breakpoints[i];
+    if (bp->active && bp->inserted) poke_byte(child, bp->address, bp->original_byte, NULL);
+    bp->active = 0;
+    bp->inserted = 0;
+  }
+
+  // signals that threads reported but we haven't dealt with still have to
+  // reach them
+  for (int i = 0; i < global_thread_count; ++i) {
+    struct thread *thread = &global_threads[i];
+    int status = thread->status;
+    int sig = thread->signal;
+    if (status && WIFSTOPPED(status) && status >> 16 == 0 && WSTOPSIG(status) != SIGTRAP && WSTOPSIG(status) != SIGSTOP)
+      sig = WSTOPSIG(status);
+
+    ptrace(PTRACE_DETACH, thread->tid, NULL, (void *)(long)sig);
+  }
+
+  // processes that we kept stopped after a fork are let go of as well
+  for (int i = 0; i < global_held_count; ++i) {
+    pid_t tids[MAX_THREADS];
+    int count = list_threads(global_held_processes[i], tids, MAX_THREADS);
+    for (int j = 0; j < count; ++j) ptrace(PTRACE_DETACH, tids[j], NULL, NULL);
+  }
+  global_held_count = 0;
+}
+
+// this function puts every active breakpoint back into a freshly started child
+// temporary breakpoints, watchpoints and their scope breakpoints belonged to the
+// previous child (the memory they watched was on its stack), so they are
+// dropped. so is the shared library breakpoint, since the dynamic linker can be
+// somewhere else now
+static void reinsert_breakpoints(pid_t child)
+{
+  for (int i = 0; i < MAX_WATCHPOINTS; ++i) global_watchpoints[i].active = 0;
+
+  for (int i = 0; i < MAX_BREAKPOINTS; ++i) {
+    struct breakpoint *bp = &global_breakpoints[i];
+    if (!bp->active) continue;
+
+    bp->inserted = 0;
+    if (bp->number == 0 || bp->number == SHLIB_BREAKPOINT || bp->number == SCOPE_BREAKPOINT)
+      bp->active = 0;
+    else if (poke_byte(child, bp->address, INT3, &bp->original_byte) == 0) bp->inserted = 1;
+  }
+}
+
+// this function makes process `pid`, which is stopped, fork. a syscall
+// instruction is written over the code at RIP for the duration, and the code
+// and registers are put back in both processes afterwards, so the new process
+// is an exact copy of `pid` without our breakpoints. it is left stopped
+// the fork is a clone without an exit signal, so that `pid` isn't sent a
+// SIGCHLD when we kill the copy
+// returns the pid of the new process, or -1 on failure
+static pid_t inject_fork(pid_t pid)
+{
+  struct user_regs_struct saved, regs;
+  if (get_regs(pid, &saved) == -1) return -1;
+
+  errno = 0;
+  long word = ptrace(PTRACE_PEEKTEXT, pid, (void *)saved.rip, NULL);
+  if (word == -1L && errno != 0) return -1;
+
+  // 0F 05 is syscall. orig_rax has to be -1 so that a system call that the
+  // process was interrupted in isn't restarted instead
+  ptrace(PTRACE_POKETEXT, pid, (void *)saved.rip, (void *)((word & ~0xFFFFL) | 0x050F));
+  regs = saved;
+  regs.rax = SYS_clone;
+  regs.rdi = 0; // flags
+  regs.rsi = 0; // the same stack
+  regs.rdx = 0;
+  regs.r10 = 0;
+  regs.r8 = 0;
+  regs.orig_rax = -1;
+  set_regs(pid, &regs);
+
+  // the clone event comes before the system call returns, and the new process
+  // starts with a SIGSTOP
+  pid_t new_pid = -1;
+  int status;
+  ptrace(PTRACE_SINGLESTEP, pid, NULL, NULL);
+  waitpid(pid, &status, __WALL);
+  if (WIFSTOPPED(status) && status >> 16 == PTRACE_EVENT_CLONE) {
+    unsigned long message;
+    ptrace(PTRACE_GETEVENTMSG, pid, NULL, &message);
+    new_pid = message;
+    if (!take_early_process(new_pid)) waitpid(new_pid, &status, __WALL);
+
+    ptrace(PTRACE_SINGLESTEP, pid, NULL, NULL);
+    waitpid(pid, &status, __WALL);
+  }
+
+  ptrace(PTRACE_POKETEXT, pid, (void *)saved.rip, (void *)word);
+  set_regs(pid, &saved);
+  if (new_pid == -1) return -1;
+
+  ptrace(PTRACE_POKETEXT, new_pid, (void *)saved.rip, (void *)word);
+  set_regs(new_pid, &saved);
+  for (int i = 0; i < MAX_BREAKPOINTS; ++i) {
+    struct breakpoint *bp = &global_breakpoints[i];
+    if (bp->active && bp->inserted) poke_byte(new_pid, bp->address, bp->original_byte, NULL);
+  }
+
+  return new_pid;
+}
+
+// this function kills process `pid`, which we trace but aren't following, and
+// waits for all of its threads to be gone. the main thread goes last
+static void kill_traced_process(pid_t pid)
+{
+  pid_t tids[MAX_THREADS];
+  int count = list_threads(pid, tids, MAX_THREADS);
+  kill(pid, SIGKILL);
+
+  for (int i = 0; i <= count; ++i) {
+    pid_t tid = i < count ? tids[i] : pid;
+    if (i < count && tid == pid) continue;
+
+    // threads can still stop on their way out
+    int status;
+    while (waitpid(tid, &status, __WALL) != -1 && WIFSTOPPED(status))
+      ptrace(PTRACE_CONT, tid, NULL, NULL);
+  }
+}
+
+// this function kills every checkpoint
+static void kill_checkpoints()
+{
+  for (int i = 0; i < global_checkpoint_count; ++i) kill_traced_process(global_checkpoints[i].pid);
+  global_checkpoint_count = 0;
+}
+
+// this function opens a new pseudo-terminal for the child process
+// returns our end of it and stores the name of the child's end in `name`
+static int open_pty(char *name, size_t size)
+{
+  // the child shouldn't inherit our end, and reading it shouldn't block us
+  int pty = open("/dev/ptmx", O_RDWR | O_NOCTTY | O_CLOEXEC | O_NONBLOCK);
+  int unlock = 0;
+  if (pty == -1 || ioctl(pty, TIOCSPTLCK, &unlock) == -1) {
+    printf("could not open a pseudo-terminal: %s\n", strerror(errno));
+    if (pty != -1) close(pty);
+    return -1;
+  }
+
+  // devpts isn't always mounted at /dev/pts, so ask where the other end is
+  int error = ptsname_r(pty, name, size);
+  if (error != 0) {
+    printf("could not open a pseudo-terminal: %s\n", strerror(error));
+    close(pty);
+    return -1;
+  }
+
+  return pty;
+}
+
+// this function prints whatever the child process has written to its
+// pseudo-terminal that we haven't shown yet
+static void print_pty_output()
+{
+  if (global_pty == -1) return;
+
+  char buffer[4096];
+  ssize_t count;
+  while ((count = read(global_pty, buffer, sizeof(buffer))) > 0)
+    fwrite(buffer, 1, count, stdout);
+  fflush(stdout);
+}
+
+// this function gets called in the child process after forking
+// it tells the parent to trace it, moves into its working directory, sets up
+// its standard streams and then execves the target
+// `io` holds the files to redirect stdin/stdout/stderr to, and `pty` is the
+// name of the pseudo-terminal to run on (both can be NULL)
+void setup_inferior(
+  const char *target,
+  char *argv[],
+  char *envp[],
+  const char *cwd,
+  char *io[3],
+  const char *pty
+  )
+{
+  ptrace(PTRACE_TRACEME, 0, NULL, NULL);
+
+  // the personality is inherited through execve
+  if (global_disable_randomization) {
+    int persona = personality(0xffffffff);
+    if (persona == -1 || personality(persona | ADDR_NO_RANDOMIZE) == -1) {
+      printf("could not disable address space randomization: %s\n", strerror(errno));
+      fflush(stdout);
+    }
+  }
+
+  if (cwd && chdir(cwd) == -1) {
+    printf("could not change directory to '%s': %s\n", cwd, strerror(errno));
+    fflush(stdout);
+    _exit(127);
+  }
+
+  // open the redirections before touching our streams, so that errors still
+  // end up on thorin's terminal
+  int fds[3] = { -1, -1, -1 };
+  for (int i = 0; i < 3; ++i) {
+    if (io[i] == NULL) continue;
+    fds[i] = i == 0 ? open(io[i], O_RDONLY) : open(io[i], O_WRONLY | O_CREAT | O_TRUNC, 0644);
+    if (fds[i] == -1) {
+      printf("could not open '%s': %s\n", io[i], strerror(errno));
+      fflush(stdout);
+      _exit(127);
+    }
+  }
+
+  if (pty) {
+    // a new session lets the pseudo-terminal become our controlling terminal
+    setsid();
+    int fd = open(pty, O_RDWR);
+    if (fd == -1) _exit(127);
+    ioctl(fd, TIOCSCTTY, 0);
+    for (int i = 0; i < 3; ++i) dup2(fd, i);
+    if (fd > 2) close(fd);
+  }
+
+  for (int i = 0; i < 3; ++i) {
+    if (fds[i] == -1) continue;
+    dup2(fds[i], i);
+    close(fds[i]);
+  }
+
+  execve(target, argv, envp);
+  printf("could not execute '%s': %s\n", target, strerror(errno));
+  fflush(stdout);
+  _exit(127);
+}
+
+// this function kills the child and waits for it to be gone
+// the child can still stop on its way out (e.g for PTRACE_EVENT_EXIT), so we
+// keep resuming it until it is. the main thread only goes away after all the
+// others have, and processes that it forked are killed too, so we wait until
+// there's nothing left that we trace
+static void kill_inferior(pid_t child, int *status)
+{
+  release_vfork_parent();
+  kill_checkpoints();
+  kill(child, SIGKILL);
+  for (int i = 0; i < global_held_count; ++i) kill(global_held_processes[i], SIGKILL);
+  for (int i = 0; i < global_early_count; ++i) kill(global_early_processes[i], SIGKILL);
+  global_held_count = 0;
+  global_early_count = 0;
+
+  // a thread that is stopped right before exiting doesn't notice the SIGKILL
+  // until it is resumed
+  for (int i = 0; i < global_thread_count; ++i) ptrace(PTRACE_CONT, global_threads[i].tid, NULL, NULL);
+
+  pid_t tid;
+  int other;
+  while ((tid = waitpid(-1, &other, __WALL)) != -1) {
+    if (WIFSTOPPED(other)) {
+      // e.g a process that was forked right before the child was killed
+      if (tid != child) kill(tid, SIGKILL);
+      ptrace(PTRACE_CONT, tid, NULL, NULL);
+    } else if (tid == child) {
+      *status = other;
+    }
+  }
+  global_thread_count = 0;
+}
+
+// this function tells the user how the child process ended and remembers it
+// `status` is the wait status of the child
+static void report_exit(int status)
+{
+  print_pty_output();
+
+  if (WIFEXITED(status)) {
+    global_exit_status = WEXITSTATUS(status);
+    printf("Child process exited with code %d\n", WEXITSTATUS(status));
+  } else if (WIFSIGNALED(status)) {
+    global_exit_status = 128 + WTERMSIG(status);
+    printf(
+      "Child process terminated by signal %d (%s)%s\n",
+      WTERMSIG(status),
+      strsignal(WTERMSIG(status)),
+      WCOREDUMP(status) ? ", core dumped" : ""
+      );
+  }
+  fflush(stdout);
+}
+
+// this function attaches to the child process and traces it until it exits
+// if we started the child, the first SIGTRAP is sent when it calls execve. at
+// that point the child hasn't run any of its own code yet, so we hand control
+// to the user to let them set breakpoints. if we attached to the child, the
+// first stop is the SIGSTOP that PTRACE_ATTACH sends it.
+// after that, SIGTRAPs are either breakpoints or something we don't care
+// about, and every other signal is treated as an exception.
+// whenever the child is suspended, the rust callback decides whether to
+// resume it, kill it or detach from it
+// returns the action that ended the session, so that the caller knows whether
+// to start the child again
+int attach_to_inferior(pid_t child, int attached) {
+  global_child = child;
+  global_thread = child;
+
+  struct sigaction handler = { 0 };
+  handler.sa_handler = interrupt_handler;
+  handler.sa_flags = SA_RESTART;
+  sigaction(SIGINT, &handler, NULL);
+
+  int started = 0;
+  int stepping = 0;
+  int watch_stepping = 0;
+  int status;
+
+  // `tid` is the thread that the current stop is for, and `syscall` is what
+  // we know about the system call it stopped at, if it did
+  pid_t tid = child;
+  struct stop_info syscall;
+  waitpid(child, &status, __WALL);
+  global_shares_terminal = getpgid(child) == getpgrp();
+  update_load_bias(child);
+  while (1) {
+    int switched = 0;
+    if (WIFEXITED(status) || WIFSIGNALED(status)) {
+      if (tid != child) {
+        // if we were stepping the thread, there's nothing left to step
+        remove_thread(tid);
+        resume_threads();
+        tid = wait_for_thread(&status);
+        continue;
+      }
+
+      report_exit(status);
+      release_vfork_parent();
+      if (global_held_count == 0) {
+        kill_checkpoints();
+        global_child_running = 0;
+        global_thread_count = 0;
+        return ACTION_KILL;
+      }
+
+      child = tid = switch_to_held_process();
+      switched = 1;
+    }
+
+    int index = find_thread(tid);
+    if (index != -1) global_threads[index].stopped = 1;
+
+    int event = switched ? 0 : status >> 16;
+    int handled = 0;
+    if (started && (event == PTRACE_EVENT_FORK || event == PTRACE_EVENT_VFORK)) {
+      pid_t followed = handle_fork(tid, event == PTRACE_EVENT_VFORK);
+      if (followed != child) child = tid = followed;
+      handled = 1;
+    } else if (started && !switched && WSTOPSIG(status) == (SIGTRAP | 0x80)) {
+      // system calls are reported while the other threads keep running, since
+      // stopping them would interrupt the system calls that they are in. only
+      // the ones that the rust side wants to stop at stop everything
+      struct user_regs_struct regs;
+      get_regs(tid, &regs);
+      memset(&syscall, 0, sizeof(syscall));
+      syscall.syscall = regs.orig_rax;
+      syscall.status = !entering_syscall(tid);
+      int reason = syscall.status ? STOP_SYSCALL_EXIT : STOP_SYSCALL_ENTRY;
+      handled = perform_callback(tid, reason, &syscall) != ACTION_STOP;
+    } else if (started && !switched) {
+      handled = handle_thread_event(tid, status);
+    }
+
+    if (handled) {
+      if (stepping || watch_stepping) {
+        step_instruction(tid, 0, &status);
+      } else {
+        resume_threads();
+        tid = wait_for_thread(&status);
+      }
+      continue;
+    }
+
+    if (event == PTRACE_EVENT_EXEC) {
+      // the parent's memory is its own again, with our breakpoints still in it
+      release_vfork_parent();
+      forget_old_image();
+      watch_shared_libraries(global_child);
+    }
+
+    // every thread stands still while the user looks at the child
+    stop_other_threads(tid);
+
+    int action = ACTION_CONTINUE;
+    int sig = 0;
+    if (!started) {
+      started = 1;
+      if (!attached) reinsert_breakpoints(child);
+      watch_shared_libraries(child);
+
+      // this makes the child stop one last time right before it exits, and
+      // tells us about new threads and processes
+      for (int i = 0; i < global_thread_count; ++i)
+        ptrace(PTRACE_SETOPTIONS, global_threads[i].tid, NULL, (void *)TRACE_OPTIONS);
+      action = perform_callback(tid, attached ? STOP_ATTACH : STOP_ENTRY, NULL);
+    } else if (switched) {
+      action = perform_callback(tid, STOP_SWITCH, NULL);
+    } else if (event == PTRACE_EVENT_EXEC) {
+      action = perform_callback(tid, STOP_EXEC, NULL);
+    } else if (WSTOPSIG(status) == (SIGTRAP | 0x80)) {
+      action = perform_callback(tid, STOP_CATCHPOINT, &syscall);
+    } else if (status >> 8 == (SIGTRAP | (PTRACE_EVENT_EXIT << 8))) {
+      // the child is exiting, and its wait status is the event message
+      struct stop_info info = { 0 };
+      unsigned long exit_status = 0;
+      ptrace(PTRACE_GETEVENTMSG, tid, NULL, &exit_status);
+      info.status = exit_status;
+      action = perform_callback(tid, STOP_EXIT, &info);
+    } else if (WSTOPSIG(status) == SIGTRAP) {
+      struct stop_info info = { 0 };
+      int watchpoint = handle_watchpoint(tid);
+      int number = watchpoint > 0 ? -1 : handle_breakpoint(tid, watch_stepping);
+      if (watchpoint > 0) {
+        info.watchpoint = watchpoint;
+        action = perform_callback(tid, STOP_WATCHPOINT, &info);
+      } else if (number > 0) {
+        info.breakpoint = number;
+        action = perform_callback(tid, STOP_BREAKPOINT, &info);
+      } else if (number == SHLIB_BREAKPOINT) {
+        action = perform_callback(tid, STOP_LIBRARIES, NULL);
+      } else if (number == SCOPE_BREAKPOINT) {
+        action = perform_callback(tid, STOP_SCOPE, NULL);
+      } else if (number == 0 || stepping)
+        action = perform_callback(tid, STOP_STEP, NULL);
+      else if (watch_stepping)
+        action = perform_callback(tid, STOP_WATCH_STEP, NULL);
+      else if (get_stop_info(tid, SIGTRAP, &info) == 0 && info.code == SI_KERNEL) {
+        // an int3 that we didn't put there, e.g a hard-coded breakpoint
+        info.trap = 1;
+        action = perform_callback(tid, STOP_EXCEPTION, &info);
+      }
+    } else if ((WSTOPSIG(status) == SIGINT && global_interrupted) ||
+               (WSTOPSIG(status) == SIGSTOP && sent_by_interrupt(tid))) {
+      // the user pressed Ctrl-C. the SIGINT only reaches the child if the
+      // user asked for it to be passed, possibly after the interrupt
+      action = perform_callback(tid, STOP_INTERRUPT, NULL);
+      if (WSTOPSIG(status) == SIGINT && global_signal_policies[SIGINT].pass) sig = SIGINT;
+    } else if (WSTOPSIG(status) == SIGSTOP && sent_by_us(tid)) {
+      // a SIGSTOP that was meant to stop this thread while it was busy
+      // reporting something else
+      if (stepping) action = ACTION_STEP;
+    } else {
+      // the signal is delivered when the child is resumed (unless it
+      // shouldn't be passed), so that the child behaves as it would have
+      // without a debugger
+      // when the child stops, the rust callback tells the user why
+      int signo = WSTOPSIG(status);
+      struct signal_policy *policy = &global_signal_policies[signo];
+      if (policy->pass) sig = signo;
+
+      struct stop_info info;
+      if (policy->stop) {
+        get_stop_info(tid, signo, &info);
+        action = perform_callback(tid, STOP_EXCEPTION, &info);
+      } else {
+        if (policy->print) printf("Program received signal %s, %s.\n", signal_name(signo), strsignal(signo));
+        if (stepping) action = ACTION_STEP;
+      }
+    }
+
+    // the user can switch to another process, e.g by restarting from a
+    // checkpoint
+    if (global_child != child) {
+      child = tid = global_thread;
+      sig = 0;
+    }
+
+    if (action == ACTION_KILL || action == ACTION_RESTART) {
+      global_child_running = 0;
+      kill_inferior(child, &status);
+      global_exit_status = 128 + SIGKILL;
+      return action;
+    }
+
+    if (action == ACTION_DETACH) {
+      global_child_running = 0;
+      kill_checkpoints();
+      detach_from_inferior(child);
+      printf("Detached from process %d\n", child);
+      fflush(stdout);
+      global_exit_status = 0;
+      global_thread_count = 0;
+      return action;
+    }
+
+    stepping = action == ACTION_STEP;
+    watch_stepping = !stepping && global_watch_stepping;
+    if ((stepping || watch_stepping) && global_thread == tid) {
+      step_instruction(tid, sig, &status);
+      continue;
+    }
+
+    // the thread that stopped has to get off its breakpoint before anything
+    // else runs, since the breakpoint is lifted
+    if (step_over_breakpoint(tid, &sig, &status)) continue;
+
+    index = find_thread(tid);
+    if (index != -1) global_threads[index].signal = sig;
+
+    // stepping only moves the thread that the user is looking at
+    if (stepping || watch_stepping) {
+      tid = global_thread;
+      step_instruction(tid, 0, &status);
+      continue;
+    }
+
+    resume_threads();
+    tid = wait_for_thread(&status);
+  }
+}
+
+#endif
+
+// this function copies a NULL-terminated array of strings
+static char **copy_strings(char *const strings[])
+{
+  int count = 0;
+  while (strings[count]) ++count;
+
+  char **copy = malloc((count + 1) * sizeof(char *));
+  for (int i = 0; i < count; ++i) copy[i] = strdup(strings[i]);
+  copy[count] = NULL;
+
+  return copy;
+}
+
+// this function frees an array of strings allocated by copy_strings
+static void free_strings(char **strings)
+{
+  if (strings == NULL) return;
+  for (int i = 0; strings[i]; ++i) free(strings[i]);
+  free(strings);
+}
+
+// this function changes the arguments, environment and working directory that
+// the child process is started with. they take effect the next time it is started
+void configure_inferior(char *const argv[], char *const envp[], const char *cwd)
+{
+  free_strings(global_argv);
+  free_strings(global_envp);
+  free(global_cwd);
+
+  global_argv = copy_strings(argv);
+  global_envp = copy_strings(envp);
+  global_cwd = cwd ? strdup(cwd) : NULL;
+}
+
+// this function changes where the standard streams of the child process go
+// `in`, `out` and `err` are files to redirect them to (or NULL), and `use_pty`
+// runs the child on its own pseudo-terminal. they take effect the next time it
+// is started
+void redirect_inferior(const char *in, const char *out, const char *err, int use_pty)
+{
+  const char *io[3] = { in, out, err };
+  for (int i = 0; i < 3; ++i) {
+    free(global_io[i]);
+    global_io[i] = io[i] ? strdup(io[i]) : NULL;
+  }
+  global_use_pty = use_pty;
+}
+
+// this function initializes global state and starts the child process
+// `argv` and `envp` are NULL-terminated, `cwd` can be NULL
+// returns how the child process ended as a shell exit status
+int setup(
+  const char *target,
+  char *const argv[],
+  char *const envp[],
+  const char *cwd,
+  exc_callback cb,
+  void *session
+  )
+{
+  global_cb = cb;
+  global_session = session;
+  init_signal_policies();
+
+  // the child can change directory before it execs, so it needs the full path
+  global_target = realpath(target, NULL);
+  if (global_target == NULL) global_target = strdup(target);
+  configure_inferior(argv, envp, cwd);
+
+  pid_t child = 0;
+
+#ifdef __APPLE__
+  posix_spawnattr_t attr;
+  posix_spawnattr_init(&attr);
+  posix_spawnattr_setflags(&attr, 0x100); // disable ASLR on MacOS
+  posix_spawnp(&child, target, NULL, &attr, global_argv, global_envp);
+
+  mach_port_t task;
+  mach_port_t task_exception_port;
+  kern_return_t kret;
+
+  kret = task_for_pid(mach_task_self(), child, &task);
+  if (kret != KERN_SUCCESS) {
+    printf("task_for_pid failed: %s\n", mach_error_string(kret));
+    return 1;
+  }
+
+  kret = mach_port_allocate(mach_task_self(), MACH_PORT_RIGHT_RECEIVE, &task_exception_port);
+  if (kret != KERN_SUCCESS) {
+    printf("mach_port_allocate failed: %s\n", mach_error_string(kret));
+    return 1;
+  }
+
+  kret = mach_port_insert_right(
+    mach_task_self(),
+    task_exception_port,
+    task_exception_port,
+    MACH_MSG_TYPE_MAKE_SEND
+    );
+  if (kret != KERN_SUCCESS) {
+    printf("mach_port_insert_right failed: %s\n", mach_error_string(kret));
+    return 1;
+  }
+
+  kret = task_set_exception_ports(
+    task,
+    EXC_MASK_ALL,
+    task_exception_port,
+    EXCEPTION_STATE_IDENTITY | MACH_EXCEPTION_CODES,
+    x86_THREAD_STATE64
+    );
+  if (kret != KERN_SUCCESS) {
+    printf("task_set_exception_ports failed: %s\n", mach_error_string(kret));
+    return 1;
+  }
+
+  global_task = task;
+  global_task_exc = task_exception_port;
+
+  size_t req_size = sizeof(union __RequestUnion__mach_exc_subsystem);
+  size_t rep_size = sizeof(union __ReplyUnion__mach_exc_subsystem);
+  mach_msg_server_once(
+    mach_exc_server,
+    req_size > rep_size ? req_size : rep_size,
+    task_exception_port,
+    0
+    );
+#elif __linux__
+  int action;
+  do {
+    action = ACTION_KILL;
+
+    // every run gets a fresh pseudo-terminal, so output from the previous
+    // child doesn't show up
+    char pty_name[128];
+    if (global_pty != -1) close(global_pty);
+    global_pty = global_use_pty ? open_pty(pty_name, sizeof(pty_name)) : -1;
+
+    fflush(stdout);
+    child = fork();
+    switch (child) {
+    case 0:
+      setup_inferior(
+        global_target, global_argv, global_envp, global_cwd,
+        global_io, global_pty != -1 ? pty_name : NULL
+        );
+      break;
+    case -1:
+      break;
+    default:
+      global_thread_count = 0;
+      global_thread_number = 0;
+      add_thread(child);
+      action = attach_to_inferior(child, 0);
+      break;
+    }
+  } while ((child == -1 && errno == EAGAIN) || action == ACTION_RESTART);
+#endif
+
+  return global_exit_status;
+}
+
+// this function initializes global state and attaches to a process that is
+// already running, stopping all of its threads
+// returns how the child process ended as a shell exit status, or 0 if we
+// detached from it
+int attach(pid_t pid, exc_callback cb, void *session)
+{
+  global_cb = cb;
+  global_session = session;
+  init_signal_policies();
+
+#ifdef __APPLE__
+  printf("attaching to a running process is not supported on MacOS yet\n");
+  return 1;
+#elif __linux__
+  char path[64];
+  snprintf(path, sizeof(path), "/proc/%d/task", pid);
+  DIR *dir = opendir(path);
+  if (dir == NULL) {
+    printf("could not list the threads of process %d: %s\n", pid, strerror(errno));
+    return 1;
+  }
+
+  struct dirent *entry;
+  while ((entry = readdir(dir)) != NULL && global_thread_count < MAX_THREADS) {
+    pid_t tid = atoi(entry->d_name);
+    if (tid <= 0) continue;
+
+    if (ptrace(PTRACE_ATTACH, tid, NULL, NULL) == -1) {
+      printf("PTRACE_ATTACH failed for thread %d: %s\n", tid, strerror(errno));
+      if (tid == pid) {
+        closedir(dir);
+        detach_from_inferior(pid);
+        return 1;
+      }
+
+      continue;
+    }
+
+    add_thread(tid);
+  }
+  closedir(dir);
+
+  // the main thread is waited on by attach_to_inferior, the others have to be
+  // waited on here so that they are known to be stopped
+  for (int i = 0; i < global_thread_count; ++i) {
+    int status;
+    if (global_threads[i].tid != pid) waitpid(global_threads[i].tid, &status, __WALL);
+    global_threads[i].stopped = 1;
+  }
+
+  attach_to_inferior(pid, 1);
+#endif
+
+  return global_exit_status;
+}
+
+// this function sets a software breakpoint at `address` in the child process
+// returns the breakpoint's number, or -1 if it could not be set
+int set_breakpoint(uintptr_t address)
+{
+#ifdef __APPLE__
+  printf("breakpoints are not supported on MacOS yet\n");
+  return -1;
+#elif __linux__
+  struct breakpoint *bp = find_breakpoint(address);
+  if (bp && bp->number != 0) {
+    printf("there is already a breakpoint at %#lx\n", address);
+    return -1;
+  }
+
+  // a temporary breakpoint at the same address becomes a real one
+  if (bp == NULL) bp = insert_breakpoint(address, 0);
+  if (bp == NULL) return -1;
+
+  bp->number = ++global_breakpoint_number;
+  return bp->number;
+#endif
+}
+
+// this function puts breakpoint `number` back at `address` after it was
+// deleted to disable it
+// returns 0 on success and -1 on failure
+int enable_breakpoint(uintptr_t address, int number)
+{
+#ifdef __APPLE__
+  return -1;
+#elif __linux__
+  struct breakpoint *bp = find_breakpoint(address);
+  if (bp && bp->number != 0) {
+    printf("breakpoint %d is already at %#lx\n", bp->number, address);
+    return -1;
+  }
+
+  if (bp == NULL) bp = insert_breakpoint(address, 0);
+  if (bp == NULL) return -1;
+
+  bp->number = number;
+  return 0;
+#endif
+}
+
+// this function removes the breakpoint or hardware watchpoint `number`
+// returns 0 on success and -1 if there is no such breakpoint or watchpoint
+int delete_breakpoint(int number)
+{
+#ifdef __linux__
+  for (int i = 0; i < MAX_BREAKPOINTS && number != 0; ++i) {
+    struct breakpoint *bp = &global_breakpoints[i];
+    if (!bp->active || bp->number != number) continue;
+
+    // a breakpoint that was just hit is lifted already
+    if (bp->inserted) poke_byte(global_child, bp->address, bp->original_byte, NULL);
+    bp->active = 0;
+    bp->inserted = 0;
+    return 0;
+  }
+
+  for (int i = 0; i < MAX_WATCHPOINTS && number != 0; ++i) {
+    struct watchpoint *wp = &global_watchpoints[i];
+    if (!wp->active || wp->number != number) continue;
+
+    wp->active = 0;
+    return write_debug_registers();
+  }
+#endif
+
+  return -1;
+}
+
+// this function forgets about breakpoint `number` without taking it out of the
+// child, for breakpoints in code that is gone (or about to be), like a shared
+// library that was unloaded
+void forget_breakpoint(int number)
+{
+#ifdef __linux__
+  for (int i = 0; i < MAX_BREAKPOINTS && number != 0; ++i) {
+    struct breakpoint *bp = &global_breakpoints[i];
+    if (!bp->active || bp->number != number) continue;
+
+    bp->active = 0;
+    bp->inserted = 0;
+  }
+#endif
+}
+
+// this function sets a hardware watchpoint on the `size` bytes at `address`
+// `kind` is one of the WATCH_* constants, and `size` has to be 1, 2, 4 or 8
+// with `address` aligned to it
+// returns the number of the new watchpoint or -1 on failure
+int set_watchpoint(uintptr_t address, int size, int kind)
+{
+#ifdef __APPLE__
+  printf("watchpoints are not supported on MacOS yet\n");
+  return -1;
+#elif __linux__
+  if ((size != 1 && size != 2 && size != 4 && size != 8) || address % size != 0) {
+    printf("can't watch %d bytes at %#lx: only 1, 2, 4 or 8 aligned bytes can be watched\n", size, address);
+    return -1;
+  }
+
+  struct watchpoint *wp = NULL;
+  for (int i = 0; i < MAX_WATCHPOINTS && wp == NULL; ++i)
+    if (!global_watchpoints[i].active) wp = &global_watchpoints[i];
+
+  if (wp == NULL) {
+    printf("cannot set more than %d watchpoints\n", MAX_WATCHPOINTS);
+    return -1;
+  }
+
+  wp->address = address;
+  wp->size = size;
+  wp->kind = kind;
+  wp->active = 1;
+  if (write_debug_registers() == -1) {
+    wp->active = 0;
+    write_debug_registers();
+    return -1;
+  }
+
+  wp->number = ++global_breakpoint_number;
+  return wp->number;
+#endif
+}
+
+// this function turns single-stepping the child instead of resuming it on
+// or off, for checking software watchpoints
+void set_watch_stepping(int on)
+{
+  global_watch_stepping = on;
+}
+
+// this function turns stopping the child at every system call on or off
+void set_syscall_tracing(int on)
+{
+  global_syscall_tracing = on;
+}
+
+// this function hands out the next breakpoint number, for things that are
+// kept track of on the rust side but numbered along with breakpoints
+int next_breakpoint_number()
+{
+  return ++global_breakpoint_number;
+}
+
+// this function changes what happens when the child process forks:
+// `follow_child` makes us follow the new process instead of the child, and
+// `detach` says whether to detach from the other one or keep it stopped
+void set_fork_mode(int follow_child, int detach)
+{
+#ifdef __linux__
+  global_follow_fork_child = follow_child;
+  global_detach_on_fork = detach;
+#endif
+}
+
+// this function turns starting the child with address space randomization
+// disabled on or off. it takes effect the next time the child is started
+void set_disable_randomization(int on)
+{
+#ifdef __linux__
+  global_disable_randomization = on;
+#endif
+}
+
+// this function returns the load bias of the child's executable, which is
+// added to the addresses in its debugging information
+uintptr_t get_load_bias()
+{
+#ifdef __APPLE__
+  return 0;
+#elif __linux__
+  return global_load_bias;
+#endif
+}
+
+// this function returns the address of the dynamic linker's list of shared
+// libraries (its struct r_debug) in the child, or 0 if it doesn't have one
+uintptr_t get_r_debug()
+{
+#ifdef __APPLE__
+  return 0;
+#elif __linux__
+  return global_r_debug;
+#endif
+}
+
+// this function returns the pid of the process that we are following
+pid_t get_process_id()
+{
+#ifdef __APPLE__
+  return 0;
+#elif __linux__
+  return global_child;
+#endif
+}
+
+// this function returns the thread id of the thread that the child stopped in,
+// and `count` is set to how many threads the child has
+pid_t get_thread_id(int *count)
+{
+#ifdef __APPLE__
+  *count = 1;
+  return 0;
+#elif __linux__
+  *count = global_thread_count;
+  return global_thread;
+#endif
+}
+
+// this function sets a temporary breakpoint at `address` in the child process
+// returns 0 on success and -1 on failure
+int set_temporary_breakpoint(uintptr_t address)
+{
+#ifdef __APPLE__
+  printf("breakpoints are not supported on MacOS yet\n");
+  return -1;
+#elif __linux__
+  // if there is a breakpoint here already, the child will stop anyway
+  if (find_breakpoint(address)) return 0;
+
+  return insert_breakpoint(address, 0) ? 0 : -1;
+#endif
+}
+
+// this function sets a scope breakpoint at `address`, which stays until it is
+// deleted like a numbered one but isn't shown to the user
+// returns 0 on success and -1 on failure
+int set_scope_breakpoint(uintptr_t address)
+{
+#ifdef __APPLE__
+  return -1;
+#elif __linux__
+  // if there is a breakpoint here already, the child will stop anyway
+  if (find_breakpoint(address)) return 0;
+
+  return insert_breakpoint(address, SCOPE_BREAKPOINT) ? 0 : -1;
+#endif
+}
+
+// this function removes the scope breakpoint at `address`, if there is one
+void delete_scope_breakpoint(uintptr_t address)
+{
+#ifdef __linux__
+  struct breakpoint *bp = find_breakpoint(address);
+  if (bp == NULL || bp->number != SCOPE_BREAKPOINT) return;
+
+  if (bp->inserted) poke_byte(global_child, bp->address, bp->original_byte, NULL);
+  bp->active = 0;
+  bp->inserted = 0;
+#endif
+}
+
+// this function removes all temporary breakpoints that have not been hit
+void clear_temporary_breakpoints()
+{
+#ifdef __linux__
+  for (int i = 0; i < MAX_BREAKPOINTS; ++i) {
+    struct breakpoint *bp = &global_breakpoints[i];
+    if (!bp->active || bp->number != 0) continue;
+
+    if (bp->inserted) poke_byte(global_child, bp->address, bp->original_byte, NULL);
+    bp->active = 0;
+    bp->inserted = 0;
+  }
+#endif
+}
+
+// this function reads the general purpose registers of the child process
+// returns 0 on success and -1 on failure
+int get_registers(struct registers *r)
+{
+#ifdef __APPLE__
+  printf("reading registers is not supported on MacOS yet\n");
+  return -1;
+#elif __linux__
+  struct user_regs_struct regs;
+  if (get_regs(global_thread, &regs) == -1) return -1;
+
+  r->rax = regs.rax; r->rbx = regs.rbx; r->rcx = regs.rcx; r->rdx = regs.rdx;
+  r->rsi = regs.rsi; r->rdi = regs.rdi; r->rbp = regs.rbp; r->rsp = regs.rsp;
+  r->r8 = regs.r8; r->r9 = regs.r9; r->r10 = regs.r10; r->r11 = regs.r11;
+  r->r12 = regs.r12; r->r13 = regs.r13; r->r14 = regs.r14; r->r15 = regs.r15;
+  r->rip = regs.rip; r->rflags = regs.eflags;
+
+  return 0;
+#endif
+}
+
+// this function finds the frame base of the child process, the same way as
+// for the callback
+// returns 0 on failure
+uintptr_t get_frame()
+{
+#ifdef __APPLE__
+  return 0;
+#elif __linux__
+  struct user_regs_struct regs;
+  if (get_regs(global_thread, &regs) == -1) return 0;
+
+  return frame_base(&regs);
+#endif
+}
+
+// this function reads the 16 XMM registers of the child process into `xmm`,
+// which must have room for 16 * 16 bytes
+// returns 0 on success and -1 on failure
+int get_xmm_registers(uint8_t *xmm)
+{
+#ifdef __APPLE__
+  printf("reading registers is not supported on MacOS yet\n");
+  return -1;
+#elif __linux__
+  struct user_fpregs_struct fpregs;
+  if (ptrace(PTRACE_GETFPREGS, global_thread, NULL, &fpregs) == -1) {
+    printf("PTRACE_GETFPREGS failed: %s\n", strerror(errno));
+    return -1;
+  }
+
+  memcpy(xmm, fpregs.xmm_space, 16 * 16);
+  return 0;
+#endif
+}
+
+// this function describes the thread at `index` in the list of threads of the
+// child process: its number, its thread id and where it is stopped
+// returns 1 if it is the thread that the user is looking at, 0 if it isn't and
+// -1 if there is no such thread
+int get_thread(int index, int *number, pid_t *tid, uintptr_t *rip)
+{
+#ifdef __APPLE__
+  return -1;
+#elif __linux__
+  if (index < 0 || index >= global_thread_count) return -1;
+
+  struct thread *thread = &global_threads[index];
+  struct user_regs_struct regs;
+  *number = thread->number;
+  *tid = thread->tid;
+  *rip = get_regs(thread->tid, &regs) == -1 ? 0 : regs.rip;
+
+  return thread->tid == global_thread;
+#endif
+}
+
+// this function switches to the thread numbered `number`, so that reading
+// registers and stepping apply to that thread
+// returns 0 on success and -1 if there is no such thread
+int select_thread(int number)
+{
+#ifdef __APPLE__
+  printf("threads are not supported on MacOS yet\n");
+  return -1;
+#elif __linux__
+  for (int i = 0; i < global_thread_count; ++i) {
+    if (global_threads[i].number != number) continue;
+
+    global_thread = global_threads[i].tid;
+    return 0;
+  }
+
+  printf("there is no thread %d\n", number);
+  return -1;
+#endif
+}
+
+// this function forks a copy of the child process that stays stopped, so
+// that the child can be started over from where it is now
+// returns the checkpoint's number, or -1 if it could not be made
+int make_checkpoint()
+{
+#ifdef __APPLE__
+  printf("checkpoints are not supported on MacOS yet\n");
+  return -1;
+#elif __linux__
+  // only the thread that calls fork is copied
+  if (global_thread_count > 1) {
+    printf("cannot make a checkpoint of a process with more than one thread\n");
+    return -1;
+  }
+  if (global_checkpoint_count == MAX_CHECKPOINTS) {
+    printf("cannot make more than %d checkpoints\n", MAX_CHECKPOINTS);
+    return -1;
+  }
+
+  pid_t pid = inject_fork(global_child);
+  if (pid == -1) {
+    printf("could not fork process %d\n", global_child);
+    return -1;
+  }
+
+  struct checkpoint *checkpoint = &global_checkpoints[global_checkpoint_count++];
+  checkpoint->pid = pid;
+  checkpoint->number = ++global_checkpoint_number;
+
+  return checkpoint->number;
+#endif
+}
+
+// this function kills checkpoint `number`
+// returns 0 on success and -1 if there is no such checkpoint
+int delete_checkpoint(int number)
+{
+#ifdef __APPLE__
+  return -1;
+#elif __linux__
+  for (int i = 0; i < global_checkpoint_count; ++i) {
+    if (global_checkpoints[i].number != number) continue;
+
+    kill_traced_process(global_checkpoints[i].pid);
+    global_checkpoints[i] = global_checkpoints[--global_checkpoint_count];
+    return 0;
+  }
+
+  printf("there is no checkpoint %d\n", number);
+  return -1;
+#endif
+}
+
+// this function forks a fresh copy of checkpoint `number` and follows it
+// instead of the child. the old child is left stopped, to be killed or
+// followed again with `switch_process`
+// returns the pid of the old child, or -1 on failure
+pid_t start_checkpoint(int number)
+{
+#ifdef __APPLE__
+  return -1;
+#elif __linux__
+  for (int i = 0; i < global_checkpoint_count; ++i) {
+    if (global_checkpoints[i].number != number) continue;
+
+    pid_t pid = inject_fork(global_checkpoints[i].pid);
+    if (pid == -1) {
+      printf("could not fork checkpoint %d\n", number);
+      return -1;
+    }
+
+    pid_t old = global_child;
+    follow_process(pid);
+    return old;
+  }
+
+  printf("there is no checkpoint %d\n", number);
+  return -1;
+#endif
+}
+
+// this function follows process `pid` instead of the child again, after
+// `start_checkpoint`
+void switch_process(pid_t pid)
+{
+#ifdef __linux__
+  follow_process(pid);
+#endif
+}
+
+// this function kills process `pid`, which we stopped following
+void kill_process(pid_t pid)
+{
+#ifdef __linux__
+  kill_traced_process(pid);
+#endif
+}
+
+// this function executes a single instruction in the current thread of the
+// child, ignoring breakpoints, for replaying a copy of the child
+// returns 0 on success and -1 if the thread stopped for anything else
+int step_process()
+{
+#ifdef __APPLE__
+  return -1;
+#elif __linux__
+  int status;
+  if (ptrace(PTRACE_SINGLESTEP, global_thread, NULL, NULL) == -1) return -1;
+  if (waitpid(global_thread, &status, __WALL) == -1) return -1;
+
+  return WIFSTOPPED(status) && WSTOPSIG(status) == SIGTRAP && status >> 16 == 0 ? 0 : -1;
+#endif
+}
+
+// this function puts the breakpoints into a child that was started from a
+// checkpoint. watchpoints and temporary breakpoints are dropped, and the shared
+// library breakpoint is set again
+void insert_breakpoints()
+{
+#ifdef __linux__
+  reinsert_breakpoints(global_child);
+  watch_shared_libraries(global_child);
+  write_debug_registers();
+#endif
+}
+
+// this function changes what happens when the child process receives the
+// signal called `name`. `stop`, `pass` and `print` are 1 to turn that behaviour
+// on, 0 to turn it off and -1 to leave it alone. stopping for a signal means
+// printing it too, so turning one on or off also affects the other
+// returns 0 on success and -1 if the signal can't be handled
+int handle_signal(const char *name, int stop, int pass, int print)
+{
+#ifdef __APPLE__
+  printf("handling signals is not supported on MacOS yet\n");
+  return -1;
+#elif __linux__
+  init_signal_policies();
+
+  int number = signal_number(name);
+  if (number == -1) {
+    printf("unrecognized signal '%s'\n", name);
+    return -1;
+  }
+  if (number == SIGTRAP || number == SIGKILL) {
+    printf("%s is used by the debugger and can't be handled\n", signal_name(number));
+    return -1;
+  }
+
+  struct signal_policy *policy = &global_signal_policies[number];
+  if (stop != -1) policy->stop = stop;
+  if (print != -1) policy->print = print;
+  if (pass != -1) policy->pass = pass;
+  if (stop == 1) policy->print = 1;
+  if (print == 0) policy->stop = 0;
+
+  printf("Signal      Stop  Print  Pass\n");
+  printf("%-11s %-5s %-6s %s\n", signal_name(number),
+         policy->stop ? "Yes" : "No", policy->print ? "Yes" : "No", policy->pass ? "Yes" : "No");
+  fflush(stdout);
+  return 0;
+#endif
+}
+
+// this function prints what happens when the child process receives each signal
+void print_signals()
+{
+#ifdef __APPLE__
+  printf("handling signals is not supported on MacOS yet\n");
+#elif __linux__
+  init_signal_policies();
+
+  printf("Signal      Stop  Print  Pass  Description\n");
+  for (size_t i = 0; i < SIGNAL_NAME_COUNT; ++i) {
+    int number = signal_names[i].number;
+    if (number == SIGTRAP || number == SIGKILL) continue;
+
+    struct signal_policy *policy = &global_signal_policies[number];
+    printf("%-11s %-5s %-6s %-5s %s\n", signal_names[i].name,
+           policy->stop ? "Yes" : "No", policy->print ? "Yes" : "No", policy->pass ? "Yes" : "No",
+           strsignal(number));
+  }
+  fflush(stdout);
+#endif
+}
+
+// this function returns the name of signal `number`, e.g "SIGSEGV"
+const char *get_signal_name(int number)
+{
+#ifdef __APPLE__
+  return "SIGNAL";
+#elif __linux__
+  return signal_name(number);
+#endif
+}
+
+// this function reads what the child process has written to its
+// pseudo-terminal into `buffer`, without waiting for more
+// returns the number of bytes read, or -1 if the child doesn't have one
+int read_inferior_output(void *buffer, size_t size)
+{
+  if (global_pty == -1) return -1;
+
+  // the read fails with EIO once the child has closed its end
+  ssize_t count = read(global_pty, buffer, size);
+  return count < 0 ? 0 : count;
+}
+
+// this function sends `buffer` to the child process as if it had been typed
+// on its pseudo-terminal
+// returns 0 on success, -1 if the child doesn't have one and -2 if writing to
+// it failed
+int write_inferior_input(const void *buffer, size_t size)
+{
+  if (global_pty == -1) return -1;
+
+  if (write(global_pty, buffer, size) == -1) {
+    printf("could not write to the program's terminal: %s\n", strerror(errno));
+    return -2;
+  }
+
+  return 0;
+}
+
+// this function reads the target process's memory into a buffer
+// in the parent/tracing process
+// `address` is the location in the target process's address space
+void read_addr(void *buffer, uintptr_t address, size_t size)
+{
+#ifdef __APPLE__
+  kern_return_t kret;
+  mach_vm_size_t local_size = size;
+  kret = mach_vm_read_overwrite(global_task, address, (mach_vm_size_t)size, (mach_vm_address_t)buffer, &local_size);
+  if (kret != KERN_SUCCESS) {
+    printf("mach_vm_read failed: %s\n", mach_error_string(kret));
+    memset(buffer, 0, size);
+    return;
+  }
+#elif __linux__
+
+  // stolen shamelessly from https://github.com/scanmem/scanmem/blob/master/ptrace.c
+
+#ifdef __GNUC__
+#define UNLIKELY(x)   __builtin_expect(!!(x), 0)
+#else
+#define UNLIKELY(x)   (x)
+#endif
+
+#ifndef MIN
+#define MIN(a, b)     ((a) < (b) ? (a) : (b))
 #endif
 
   size_t nread = 0;
@@ -289,7 +2664,7 @@ void read_addr(void *buffer, uintptr_t address, size_t size)
             continue;
 
           uint8_t* new_memory_ptr = (uint8_t*)(&ptraced_long) + j;
-          memcpy(buffer + nread, new_memory_ptr, sizeof(long) - j);
+          memcpy(buffer + nread, new_memory_ptr, MIN(sizeof(long) - j, size - nread));
           nread += sizeof(long) - j;
           break;
         }
@@ -298,7 +2673,8 @@ void read_addr(void *buffer, uintptr_t address, size_t size)
       break;
     }
 
-    memcpy(buffer + nread, &ptraced_long, sizeof(long));
+    // the last word can go past the end of `buffer`, which may be smaller
+    memcpy(buffer + nread, &ptraced_long, MIN(sizeof(long), size - nread));
   }
 #endif
 }